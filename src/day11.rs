@@ -1,7 +1,10 @@
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
 
 use crate::util::read_data_from_file;
 
+#[cfg(test)]
 fn split_stone(stone: &str) -> (usize, usize) {
     let n_digits = stone.chars().count();
     if n_digits % 2 != 0 {
@@ -13,21 +16,234 @@ fn split_stone(stone: &str) -> (usize, usize) {
     (stone_1, stone_2)
 }
 
-fn blink(input: &Vec<usize>) -> Vec<usize> {
-    let mut new_list: Vec<usize> = Vec::new();
-    for stone in input.iter() {
-        let stone_str = stone.to_string();
-        if *stone == 0 {
-            new_list.push(1);
-        } else if stone_str.chars().count() % 2 == 0 {
-            let new_stones = split_stone(&stone_str);
-            new_list.push(new_stones.0);
-            new_list.push(new_stones.1);
-        } else {
-            new_list.push(stone * 2024);
+/// What a rule looks at on a stone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subject {
+    /// The number engraved on the stone.
+    Stone,
+    /// How many digits that number has.
+    Digits,
+}
+
+impl Subject {
+    fn of(&self, stone: u128) -> u128 {
+        match self {
+            Subject::Stone => stone,
+            Subject::Digits => digits(stone) as u128,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Predicate {
+    Always,
+    Equals(Subject, u128),
+    /// `subject % modulus == remainder`
+    Modulo(Subject, u128, u128),
+}
+
+impl Predicate {
+    fn matches(&self, stone: u128) -> bool {
+        match self {
+            Predicate::Always => true,
+            Predicate::Equals(subject, value) => subject.of(stone) == *value,
+            Predicate::Modulo(subject, modulus, remainder) => {
+                subject.of(stone) % modulus == *remainder
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Replace the stone with this number.
+    Set(u128),
+    Multiply(u128),
+    Add(u128),
+    /// Cut the digits into this many stones, the earlier ones taking any
+    /// extra digit, and dropping leading zeros.
+    Split(u32),
+}
+
+impl Action {
+    fn apply(&self, stone: u128) -> Result<Vec<u128>, BlinkError> {
+        let overflow = BlinkError::StoneOverflow { stone };
+        match self {
+            Action::Set(value) => Ok(vec![*value]),
+            Action::Multiply(factor) => Ok(vec![stone.checked_mul(*factor).ok_or(overflow)?]),
+            Action::Add(value) => Ok(vec![stone.checked_add(*value).ok_or(overflow)?]),
+            Action::Split(parts) => {
+                let n_digits = digits(stone);
+                let parts = (*parts).clamp(1, n_digits);
+                let mut rest = stone;
+                let mut remaining_digits = n_digits;
+                let mut stones = Vec::new();
+                for part in 0..parts {
+                    let size = n_digits / parts + u32::from(part < n_digits % parts);
+                    remaining_digits -= size;
+                    let place = 10u128.pow(remaining_digits);
+                    stones.push(rest / place);
+                    rest %= place;
+                }
+                Ok(stones)
+            }
         }
     }
-    new_list
+}
+
+fn digits(stone: u128) -> u32 {
+    stone.checked_ilog10().unwrap_or(0) + 1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub predicate: Predicate,
+    pub action: Action,
+}
+
+/// Rules tried in order on every stone; the first match decides what the
+/// stone becomes, and a stone no rule matches stays as it is.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+/// The rules from the puzzle, in the text format.
+pub const STANDARD_RULES: &str = "stone == 0 -> = 1
+digits % 2 == 0 -> split 2
+always -> * 2024
+";
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    pub fn rule(mut self, predicate: Predicate, action: Action) -> Self {
+        self.rules.push(Rule { predicate, action });
+        self
+    }
+
+    /// 0 becomes 1, an even number of digits splits in two, otherwise × 2024.
+    pub fn standard() -> Self {
+        RuleSet::new()
+            .rule(Predicate::Equals(Subject::Stone, 0), Action::Set(1))
+            .rule(Predicate::Modulo(Subject::Digits, 2, 0), Action::Split(2))
+            .rule(Predicate::Always, Action::Multiply(2024))
+    }
+
+    fn apply(&self, stone: u128) -> Result<Vec<u128>, BlinkError> {
+        match self.rules.iter().find(|rule| rule.predicate.matches(stone)) {
+            Some(rule) => rule.action.apply(stone),
+            None => Ok(vec![stone]),
+        }
+    }
+}
+
+fn parse_number(token: Option<&str>) -> Result<u128, String> {
+    let token = token.ok_or("missing number")?;
+    token
+        .parse::<u128>()
+        .map_err(|_| format!("expected a number, found '{token}'"))
+}
+
+fn parse_predicate(text: &str) -> Result<Predicate, String> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let subject = match tokens.first() {
+        Some(&"always") | Some(&"_") if tokens.len() == 1 => return Ok(Predicate::Always),
+        Some(&"stone") => Subject::Stone,
+        Some(&"digits") => Subject::Digits,
+        _ => return Err(format!("unknown condition '{text}'")),
+    };
+    match tokens[1..] {
+        ["==", value] => Ok(Predicate::Equals(subject, parse_number(Some(value))?)),
+        ["%", modulus, "==", remainder] => {
+            let modulus = parse_number(Some(modulus))?;
+            if modulus == 0 {
+                return Err("modulus must not be 0".to_string());
+            }
+            Ok(Predicate::Modulo(
+                subject,
+                modulus,
+                parse_number(Some(remainder))?,
+            ))
+        }
+        _ => Err(format!("unknown condition '{text}'")),
+    }
+}
+
+fn parse_action(text: &str) -> Result<Action, String> {
+    let text = text.trim();
+    if let Some(rest) = text.strip_prefix("split") {
+        let rest = rest.trim().trim_start_matches("in ").trim();
+        let parts = match rest {
+            "two" => 2,
+            "three" => 3,
+            _ => parse_number(Some(rest))?,
+        };
+        return match u32::try_from(parts) {
+            Ok(parts) if parts > 0 => Ok(Action::Split(parts)),
+            _ => Err(format!("can't split into {parts} stones")),
+        };
+    }
+    for (prefix, action) in [
+        ("×", Action::Multiply as fn(u128) -> Action),
+        ("*", Action::Multiply),
+        ("x", Action::Multiply),
+        ("+", Action::Add),
+        ("=", Action::Set),
+    ] {
+        if let Some(rest) = text.strip_prefix(prefix) {
+            return Ok(action(parse_number(Some(rest.trim()))?));
+        }
+    }
+    Err(format!("unknown action '{text}'"))
+}
+
+/// Reads one `condition -> action` rule per line, with `→` also accepted as
+/// the arrow. Conditions are `always`, `stone == N`, `stone % M == R`,
+/// `digits == N` or `digits % M == R`; actions are `= N`, `× N` (or `* N`,
+/// `x N`), `+ N` and `split N` (or `split in two`). Blank lines and `#`
+/// comments are skipped.
+impl FromStr for RuleSet {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut rules = RuleSet::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (predicate, action) = line
+                .split_once("->")
+                .or_else(|| line.split_once('→'))
+                .ok_or(format!("line {}: expected 'condition -> action'", n + 1))?;
+            let predicate =
+                parse_predicate(predicate).map_err(|e| format!("line {}: {e}", n + 1))?;
+            let action = parse_action(action).map_err(|e| format!("line {}: {e}", n + 1))?;
+            rules = rules.rule(predicate, action);
+        }
+        Ok(rules)
+    }
+}
+
+/// One blink over the whole line of stones, in order.
+fn blink_with(stones: &[u128], rules: &RuleSet) -> Result<Vec<u128>, BlinkError> {
+    let mut new_list: Vec<u128> = Vec::new();
+    for stone in stones {
+        new_list.extend(rules.apply(*stone)?);
+    }
+    Ok(new_list)
+}
+
+fn blink(input: &Vec<usize>) -> Vec<usize> {
+    let stones: Vec<u128> = input.iter().map(|x| *x as u128).collect();
+    blink_with(&stones, &RuleSet::standard())
+        .expect("Stone overflowed")
+        .into_iter()
+        .map(|x| usize::try_from(x).expect("Stone too big for usize"))
+        .collect()
 }
 
 fn process_input(input: &str) -> Vec<usize> {
@@ -46,6 +262,8 @@ pub fn part_1(input_uri: &str) -> usize {
     stones.len()
 }
 
+/// The puzzle's rules written out directly, independent of `RuleSet`.
+#[cfg(test)]
 fn blink_stone(stone: usize) -> Vec<usize> {
     let mut new_stones: Vec<usize> = Vec::new();
     let stone_str = stone.to_string();
@@ -61,6 +279,9 @@ fn blink_stone(stone: usize) -> Vec<usize> {
     new_stones
 }
 
+/// Recursive count memoized on (stone, blinks left) using `blink_stone`, kept
+/// as a reference for the rule-based engines.
+#[cfg(test)]
 fn split_stone_recursive(
     stone: usize,
     current_blink: usize,
@@ -87,15 +308,183 @@ fn split_stone_recursive(
     count_after_blink
 }
 
-pub fn part_2(input_uri: &str) -> usize {
+/// How many stones `stone` becomes after `blinks` blinks, memoized on
+/// (stone, blinks left). `total_blinks` is only used to number the
+/// generation in errors.
+fn count_memoized(
+    stone: u128,
+    blinks: usize,
+    total_blinks: usize,
+    rules: &RuleSet,
+    cache: &mut HashMap<(u128, usize), u128>,
+) -> Result<u128, BlinkError> {
+    if blinks == 0 {
+        return Ok(1);
+    }
+    if let Some(count) = cache.get(&(stone, blinks)) {
+        return Ok(*count);
+    }
+    let mut count: u128 = 0;
+    for new_stone in rules.apply(stone)? {
+        let generation = total_blinks - blinks + 1;
+        count = count
+            .checked_add(count_memoized(
+                new_stone,
+                blinks - 1,
+                total_blinks,
+                rules,
+                cache,
+            )?)
+            .ok_or(BlinkError::CountOverflow { generation })?;
+    }
+    cache.insert((stone, blinks), count);
+    Ok(count)
+}
+
+/// A blink that no longer fits in a `u128`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlinkError {
+    /// A rule's arithmetic on this stone overflowed.
+    StoneOverflow { stone: u128 },
+    /// The number of stones overflowed during this generation.
+    CountOverflow { generation: usize },
+}
+
+impl Display for BlinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlinkError::StoneOverflow { stone } => {
+                write!(f, "stone {stone} overflows when changed")
+            }
+            BlinkError::CountOverflow { generation } => {
+                write!(f, "stone count overflows at blink {generation}")
+            }
+        }
+    }
+}
+
+/// Stone counts after every blink, keyed on stone number since order never
+/// matters for the count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub counts: HashMap<u128, u128>,
+    /// Total stones at each generation, starting with the input.
+    pub totals: Vec<u128>,
+    /// Different stone numbers at each generation, starting with the input.
+    pub distinct: Vec<usize>,
+    rules: RuleSet,
+}
+
+impl Simulation {
+    pub fn new(stones: &[u128]) -> Self {
+        Simulation::with_rules(stones, RuleSet::standard())
+    }
+
+    pub fn with_rules(stones: &[u128], rules: RuleSet) -> Self {
+        let mut counts: HashMap<u128, u128> = HashMap::new();
+        for stone in stones {
+            *counts.entry(*stone).or_insert(0) += 1;
+        }
+        Simulation {
+            totals: vec![stones.len() as u128],
+            distinct: vec![counts.len()],
+            counts,
+            rules,
+        }
+    }
+
+    pub fn total(&self) -> u128 {
+        *self.totals.last().unwrap()
+    }
+
+    pub fn blink(&mut self) -> Result<(), BlinkError> {
+        let generation = self.totals.len();
+        let overflow = BlinkError::CountOverflow { generation };
+        let mut next: HashMap<u128, u128> = HashMap::new();
+        let mut total: u128 = 0;
+        for (stone, count) in self.counts.iter() {
+            for new_stone in self.rules.apply(*stone)? {
+                let entry = next.entry(new_stone).or_insert(0);
+                *entry = entry.checked_add(*count).ok_or(overflow.clone())?;
+                total = total.checked_add(*count).ok_or(overflow.clone())?;
+            }
+        }
+        self.counts = next;
+        self.totals.push(total);
+        self.distinct.push(self.counts.len());
+        Ok(())
+    }
+
+    pub fn run(&mut self, blinks: usize) -> Result<u128, BlinkError> {
+        for _ in 0..blinks {
+            self.blink()?;
+        }
+        Ok(self.total())
+    }
+}
+
+fn parse_stones(input: &str) -> Vec<u128> {
+    input
+        .split_ascii_whitespace()
+        .map(|x| x.parse::<u128>().expect("Can't convert to u128"))
+        .collect()
+}
+
+/// Runs any number of blinks on the stones in the file.
+pub fn simulate(input_uri: &str, blinks: usize) -> Result<Simulation, BlinkError> {
+    simulate_with_rules(input_uri, RuleSet::standard(), blinks)
+}
+
+/// `simulate` with a different rule set, e.g. one read with `str::parse`.
+pub fn simulate_with_rules(
+    input_uri: &str,
+    rules: RuleSet,
+    blinks: usize,
+) -> Result<Simulation, BlinkError> {
     let input = read_data_from_file(input_uri);
-    let stones = process_input(&input);
-    let mut result: usize = 0;
-    let mut stone_level_cache: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut simulation = Simulation::with_rules(&parse_stones(&input), rules);
+    simulation.run(blinks)?;
+    Ok(simulation)
+}
+
+/// The stone count from `simulate_with_rules`, worked out per stone with the
+/// memoized engine instead.
+pub fn count_with_rules(
+    input_uri: &str,
+    rules: &RuleSet,
+    blinks: usize,
+) -> Result<u128, BlinkError> {
+    let input = read_data_from_file(input_uri);
+    count_stones(&parse_stones(&input), rules, blinks)
+}
+
+/// Sums `count_memoized` over the stones. A sum only overflows once the whole
+/// line does, so on overflow the generation where that first happened is
+/// found by replaying with `Simulation`.
+fn count_stones(stones: &[u128], rules: &RuleSet, blinks: usize) -> Result<u128, BlinkError> {
+    let mut cache: HashMap<(u128, usize), u128> = HashMap::new();
+    let mut total: u128 = 0;
     for stone in stones {
-        result += split_stone_recursive(stone, 0, 75, &mut stone_level_cache)
+        let count = count_memoized(*stone, blinks, blinks, rules, &mut cache);
+        match count.map(|count| total.checked_add(count)) {
+            Ok(Some(sum)) => total = sum,
+            Err(BlinkError::StoneOverflow { stone }) => {
+                return Err(BlinkError::StoneOverflow { stone })
+            }
+            _ => {
+                let mut simulation = Simulation::with_rules(stones, rules.clone());
+                return Err(simulation.run(blinks).unwrap_err());
+            }
+        }
+    }
+    Ok(total)
+}
+
+pub fn part_2(input_uri: &str) -> u128 {
+    match simulate(input_uri, 75) {
+        Ok(simulation) => simulation.total(),
+        Err(e) => panic!("{e}"),
     }
-    result
 }
 
 #[cfg(test)]
@@ -145,4 +534,138 @@ mod tests {
         }
         assert_eq!(result, 55312);
     }
+
+    #[test]
+    fn test_simulation() {
+        let mut simulation = Simulation::new(&parse_stones("125 17"));
+        assert_eq!(simulation.run(6), Ok(22));
+        assert_eq!(simulation.run(19), Ok(55312));
+        assert_eq!(simulation.totals[..4], [2, 3, 4, 5]);
+        // 253000 1 7, then 253 0 2024 14168, then 512072 1 20 24 28676032
+        assert_eq!(simulation.distinct[..4], [2, 3, 4, 5]);
+        assert_eq!(simulation.distinct.len(), 26);
+
+        let mut naive = vec![125, 17];
+        for _ in 0..25 {
+            naive = blink(&naive);
+        }
+        let mut expected: HashMap<u128, u128> = HashMap::new();
+        for stone in naive {
+            *expected.entry(stone as u128).or_insert(0) += 1;
+        }
+        assert_eq!(simulation.counts, expected);
+    }
+
+    #[test]
+    fn test_simulation_overflow() {
+        // 39 digits, so it is multiplied rather than split
+        let mut simulation = Simulation::new(&[u128::MAX]);
+        assert_eq!(
+            simulation.blink(),
+            Err(BlinkError::StoneOverflow { stone: u128::MAX })
+        );
+
+        // Counts grow by about half each blink, so u128 runs out eventually
+        let mut simulation = Simulation::new(&[0]);
+        let error = simulation.run(1000).unwrap_err();
+        let BlinkError::CountOverflow { generation } = error else {
+            panic!("{error}")
+        };
+        assert_eq!(simulation.totals.len(), generation);
+        assert!(simulation.total() > u128::MAX / 2);
+        // The memoized engine names the same generation
+        assert_eq!(
+            count_stones(&[0], &RuleSet::standard(), 1000),
+            Err(BlinkError::CountOverflow { generation })
+        );
+        assert_eq!(
+            count_stones(&[u128::MAX], &RuleSet::standard(), 3),
+            Err(BlinkError::StoneOverflow { stone: u128::MAX })
+        );
+    }
+
+    #[test]
+    fn test_rule_parsing() {
+        assert_eq!(STANDARD_RULES.parse::<RuleSet>(), Ok(RuleSet::standard()));
+        let rules: RuleSet = "# a variant
+            digits % 3 == 0 → split in three
+            stone == 7 -> + 3
+            always -> ×3"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rules,
+            RuleSet::new()
+                .rule(Predicate::Modulo(Subject::Digits, 3, 0), Action::Split(3))
+                .rule(Predicate::Equals(Subject::Stone, 7), Action::Add(3))
+                .rule(Predicate::Always, Action::Multiply(3))
+        );
+        assert_eq!(
+            "always -> split 0".parse::<RuleSet>(),
+            Err("line 1: can't split into 0 stones".to_string())
+        );
+        assert!("stone % 0 == 1 -> = 1".parse::<RuleSet>().is_err());
+        assert!("stone > 1 -> = 1".parse::<RuleSet>().is_err());
+        assert!("\nalways = 1"
+            .parse::<RuleSet>()
+            .unwrap_err()
+            .starts_with("line 2"));
+    }
+
+    #[test]
+    fn test_actions() {
+        assert_eq!(Action::Split(3).apply(123456), Ok(vec![12, 34, 56]));
+        assert_eq!(Action::Split(3).apply(12345), Ok(vec![12, 34, 5]));
+        assert_eq!(Action::Split(2).apply(1000), Ok(vec![10, 0]));
+        assert_eq!(Action::Split(4).apply(12), Ok(vec![1, 2]));
+        assert_eq!(Action::Split(2).apply(0), Ok(vec![0]));
+        assert_eq!(
+            Action::Add(1).apply(u128::MAX),
+            Err(BlinkError::StoneOverflow { stone: u128::MAX })
+        );
+        // No rule matches, so the stone stays
+        let rules = RuleSet::new().rule(Predicate::Equals(Subject::Stone, 1), Action::Set(2));
+        assert_eq!(blink_with(&[1, 5], &rules), Ok(vec![2, 5]));
+    }
+
+    #[test]
+    fn test_engines_agree() {
+        let standard = RuleSet::standard();
+        let variant: RuleSet = "stone == 0 -> = 1
+            digits % 3 == 0 -> split 3
+            stone % 2 == 0 -> split 2
+            always -> * 7"
+            .parse()
+            .unwrap();
+        for (rules, blinks) in [(standard, 25), (variant, 15)] {
+            let stones = [125, 17, 0, 999];
+            let mut naive = stones.to_vec();
+            let mut cache = HashMap::new();
+            let mut simulation = Simulation::with_rules(&stones, rules.clone());
+            for blink in 1..=blinks {
+                naive = blink_with(&naive, &rules).unwrap();
+                let memoized: u128 = stones
+                    .iter()
+                    .map(|stone| count_memoized(*stone, blink, blink, &rules, &mut cache).unwrap())
+                    .sum();
+                simulation.blink().unwrap();
+                assert_eq!(naive.len() as u128, memoized);
+                assert_eq!(simulation.total(), memoized);
+            }
+        }
+        let mut cache = HashMap::new();
+        let count = count_memoized(125, 25, 25, &RuleSet::standard(), &mut cache).unwrap()
+            + count_memoized(17, 25, 25, &RuleSet::standard(), &mut cache).unwrap();
+        assert_eq!(count, 55312);
+
+        // The string-based reference agrees with the rule-based engines
+        let mut cache = HashMap::new();
+        let reference = split_stone_recursive(125, 0, 25, &mut cache)
+            + split_stone_recursive(17, 0, 25, &mut cache);
+        assert_eq!(reference, 55312);
+        for stone in [0, 1, 10, 99, 999, 2024] {
+            let expected: Vec<u128> = blink_stone(stone).iter().map(|x| *x as u128).collect();
+            assert_eq!(RuleSet::standard().apply(stone as u128), Ok(expected));
+        }
+    }
 }
@@ -1,176 +1,390 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 
 use crate::util::{input_to_grid, read_data_from_file};
 
-fn recursive_region_search(
-    row: usize,
-    col: usize,
-    plant_type: char,
-    region_number: usize,
-    regions: &mut HashMap<char, HashMap<usize, (HashSet<(usize, usize)>, usize)>>,
-    unvisited: &mut HashSet<(usize, usize)>,
-    grid: &Vec<Vec<char>>,
-) {
-    unvisited.remove(&(row, col));
-
-    let mut bordering: usize = 0;
-    for (plus_row, plus_col) in vec![(-1, 0), (0, 1), (1, 0), (0, -1)].iter() {
-        let next_row = match row.checked_add_signed(*plus_row) {
-            Some(a) => {
-                if a < grid.len() {
-                    a
-                } else {
-                    bordering += 1;
-                    continue;
-                }
-            }
-            None => {
-                bordering += 1;
+const NEIGHBOURS: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+const DIAGONALS: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, 1), (1, -1)];
+
+/// A connected patch of one plant type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    /// Position in reading order of each region's first cell.
+    pub id: usize,
+    pub plant: char,
+    /// In the order the flood fill reached them, starting top left.
+    pub cells: Vec<(usize, usize)>,
+    pub perimeter: usize,
+    pub sides: usize,
+    /// Top-left and bottom-right cells, inclusive.
+    pub bounding_box: ((usize, usize), (usize, usize)),
+}
+
+impl Region {
+    pub fn area(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Area times perimeter, as in part 1.
+    pub fn fence_price(&self) -> usize {
+        self.area() * self.perimeter
+    }
+
+    /// Area times number of sides, as in part 2.
+    pub fn bulk_price(&self) -> usize {
+        self.area() * self.sides
+    }
+}
+
+/// The regions of a map, with the region id of every cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Garden {
+    pub regions: Vec<Region>,
+    pub labels: Vec<Vec<usize>>,
+}
+
+impl Garden {
+    /// The region id at `(row, col)` offset by `step`, or `None` off the map.
+    fn label_at(&self, (row, col): (usize, usize), (di, dj): (isize, isize)) -> Option<usize> {
+        let row = row.checked_add_signed(di)?;
+        let col = col.checked_add_signed(dj)?;
+        self.labels.get(row)?.get(col).copied()
+    }
+}
+
+/// Labels every region with a queue-based flood fill, so region size is not
+/// limited by the stack, then counts fences and sides from the labels.
+fn find_regions(grid: &[Vec<char>]) -> Garden {
+    let mut labels: Vec<Vec<Option<usize>>> =
+        grid.iter().map(|row| vec![None; row.len()]).collect();
+    let mut regions: Vec<Region> = Vec::new();
+    for (i, row) in grid.iter().enumerate() {
+        for (j, plant) in row.iter().enumerate() {
+            if labels[i][j].is_some() {
                 continue;
             }
-        };
-        let next_col = match col.checked_add_signed(*plus_col) {
-            Some(a) => {
-                if a < grid[0].len() {
-                    a
-                } else {
-                    bordering += 1;
-                    continue;
+            let id = regions.len();
+            let mut cells = Vec::new();
+            let mut queue = VecDeque::from([(i, j)]);
+            labels[i][j] = Some(id);
+            while let Some((row, col)) = queue.pop_front() {
+                cells.push((row, col));
+                for (di, dj) in NEIGHBOURS {
+                    let (Some(next_row), Some(next_col)) =
+                        (row.checked_add_signed(di), col.checked_add_signed(dj))
+                    else {
+                        continue;
+                    };
+                    if grid.get(next_row).and_then(|r| r.get(next_col)) == Some(plant)
+                        && labels[next_row][next_col].is_none()
+                    {
+                        labels[next_row][next_col] = Some(id);
+                        queue.push_back((next_row, next_col));
+                    }
                 }
             }
-            None => {
-                bordering += 1;
-                continue;
+            let min_row = cells.iter().map(|c| c.0).min().unwrap();
+            let max_row = cells.iter().map(|c| c.0).max().unwrap();
+            let min_col = cells.iter().map(|c| c.1).min().unwrap();
+            let max_col = cells.iter().map(|c| c.1).max().unwrap();
+            regions.push(Region {
+                id,
+                plant: *plant,
+                cells,
+                perimeter: 0,
+                sides: 0,
+                bounding_box: ((min_row, min_col), (max_row, max_col)),
+            });
+        }
+    }
+    let mut garden = Garden {
+        regions,
+        labels: labels
+            .into_iter()
+            .map(|row| row.into_iter().map(|l| l.unwrap()).collect())
+            .collect(),
+    };
+    for id in 0..garden.regions.len() {
+        let mut perimeter = 0;
+        let mut corners = 0;
+        for cell in &garden.regions[id].cells {
+            let same = |step| garden.label_at(*cell, step) == Some(id);
+            perimeter += NEIGHBOURS.iter().filter(|step| !same(**step)).count();
+            // A region has as many sides as corners, outer or inner
+            for (di, dj) in DIAGONALS {
+                let vertical = same((di, 0));
+                let horizontal = same((0, dj));
+                if (!vertical && !horizontal) || (vertical && horizontal && !same((di, dj))) {
+                    corners += 1;
+                }
             }
-        };
-        if unvisited.contains(&(next_row, next_col)) & (grid[next_row][next_col] == plant_type) {
-            recursive_region_search(
-                next_row,
-                next_col,
-                plant_type,
-                region_number,
-                regions,
-                unvisited,
-                grid,
-            );
         }
-        if grid[next_row][next_col] != plant_type {
-            bordering += 1;
+        garden.regions[id].perimeter = perimeter;
+        garden.regions[id].sides = corners;
+    }
+    garden
+}
+
+/// Shared fence segments between each pair of bordering regions, keyed on
+/// (lower id, higher id).
+fn adjacency(garden: &Garden) -> BTreeMap<(usize, usize), usize> {
+    let mut edges: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for (i, row) in garden.labels.iter().enumerate() {
+        for (j, label) in row.iter().enumerate() {
+            // Right and down only, so each segment is counted once
+            for step in [(0, 1), (1, 0)] {
+                match garden.label_at((i, j), step) {
+                    Some(other) if other != *label => {
+                        *edges
+                            .entry((*label.min(&other), *label.max(&other)))
+                            .or_insert(0) += 1;
+                    }
+                    _ => (),
+                }
+            }
         }
     }
+    edges
+}
 
-    let region = regions.entry(plant_type).or_default();
-    let (garden_plots, perimeter) = region.entry(region_number).or_default();
-    garden_plots.insert((row, col));
-    *perimeter += bordering;
+/// Cells a region surrounds that can't reach the edge of the map without
+/// crossing it, and the regions found there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hole {
+    pub cells: Vec<(usize, usize)>,
+    pub regions: Vec<usize>,
 }
 
-fn find_regions(
-    grid: &Vec<Vec<char>>,
-) -> HashMap<char, HashMap<usize, (HashSet<(usize, usize)>, usize)>> {
-    let mut regions: HashMap<char, HashMap<usize, (HashSet<(usize, usize)>, usize)>> =
-        HashMap::new();
-    let mut unvisited: HashSet<(usize, usize)> = HashSet::new();
-    for (i, row) in grid.iter().enumerate() {
-        for (j, _) in row.iter().enumerate() {
-            unvisited.insert((i, j));
+/// The holes in a region. Only its bounding box needs searching, as anything
+/// outside that is open to the edge of the map.
+fn holes(garden: &Garden, id: usize) -> Vec<Hole> {
+    let ((min_row, min_col), (max_row, max_col)) = garden.regions[id].bounding_box;
+    let inside = |(row, col): (usize, usize)| {
+        (min_row..=max_row).contains(&row) && (min_col..=max_col).contains(&col)
+    };
+    let mut visited: HashSet<(usize, usize)> = HashSet::new();
+    let fill = |start: (usize, usize), visited: &mut HashSet<(usize, usize)>| {
+        let mut cells = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        visited.insert(start);
+        while let Some(cell) = queue.pop_front() {
+            cells.push(cell);
+            for (di, dj) in NEIGHBOURS {
+                let (Some(row), Some(col)) =
+                    (cell.0.checked_add_signed(di), cell.1.checked_add_signed(dj))
+                else {
+                    continue;
+                };
+                if inside((row, col)) && garden.labels[row][col] != id && visited.insert((row, col))
+                {
+                    queue.push_back((row, col));
+                }
+            }
+        }
+        cells
+    };
+    let on_border = |(row, col): (usize, usize)| {
+        row == min_row || row == max_row || col == min_col || col == max_col
+    };
+    let boxed: Vec<(usize, usize)> = (min_row..=max_row)
+        .flat_map(|row| (min_col..=max_col).map(move |col| (row, col)))
+        .filter(|(row, col)| garden.labels[*row][*col] != id)
+        .collect();
+    for cell in boxed.iter().filter(|cell| on_border(**cell)) {
+        if !visited.contains(cell) {
+            fill(*cell, &mut visited);
         }
     }
-    while !unvisited.is_empty() {
-        let (row, col) = unvisited.iter().next().unwrap();
-        let plant_type = grid[*row][*col];
-        let region_number = match regions.get(&plant_type) {
-            Some(regions) => match regions.keys().max() {
-                Some(v) => v + 1,
-                None => 0,
-            },
-            None => 0,
-        };
-        recursive_region_search(
-            *row,
-            *col,
-            plant_type,
-            region_number,
-            &mut regions,
-            &mut unvisited,
-            grid,
-        );
+    let mut holes = Vec::new();
+    for cell in &boxed {
+        if !visited.contains(cell) {
+            let cells = fill(*cell, &mut visited);
+            let mut regions: Vec<usize> =
+                cells.iter().map(|(i, j)| garden.labels[*i][*j]).collect();
+            regions.sort();
+            regions.dedup();
+            holes.push(Hole { cells, regions });
+        }
     }
-    regions
+    holes
 }
 
-pub fn part_1(input_uri: &str) -> usize {
-    let input = read_data_from_file(input_uri);
-    let grid = input_to_grid::<char>(&input);
-    let regions: HashMap<char, HashMap<usize, (HashSet<(usize, usize)>, usize)>> =
-        find_regions(&grid);
-    let mut total: usize = 0;
-    for (_, r) in regions.iter() {
-        for (_, (garden_plots, perimeter)) in r.iter() {
-            total += garden_plots.len() * perimeter;
+/// Regions fenced in by exactly one other region, as inner id to outer id.
+/// A region is enclosed when it is the only one in a hole touching the
+/// region around it; anything else in that hole is inside it in turn.
+fn enclosures(garden: &Garden) -> BTreeMap<usize, usize> {
+    let edges = adjacency(garden);
+    let borders = |a: usize, b: usize| edges.contains_key(&(a.min(b), a.max(b)));
+    let mut enclosed = BTreeMap::new();
+    for outer in 0..garden.regions.len() {
+        for hole in holes(garden, outer) {
+            let touching: Vec<usize> = hole
+                .regions
+                .iter()
+                .copied()
+                .filter(|inner| borders(*inner, outer))
+                .collect();
+            if let [inner] = touching[..] {
+                enclosed.insert(inner, outer);
+            }
         }
     }
-    total
+    enclosed
 }
 
-pub fn part_2(input_uri: &str) -> usize {
-    let input = read_data_from_file(input_uri);
-    let grid = input_to_grid::<char>(&input);
-    let regions: HashMap<char, HashMap<usize, (HashSet<(usize, usize)>, usize)>> =
-        find_regions(&grid);
-
-    let mut corner_count: HashMap<(char, usize), usize> = HashMap::new();
-    let mut new_grid: Vec<Vec<(char, usize)>> = Vec::new();
-    for i in 0..grid.len() + 2 {
-        new_grid.push(Vec::new());
-        for _ in 0..grid[0].len() + 2 {
-            new_grid[i].push(('.', 0));
-        }
+/// The region adjacency graph in Graphviz DOT, with shared fence segments on
+/// each edge and enclosing borders drawn bold.
+fn to_dot(garden: &Garden) -> String {
+    let enclosed = enclosures(garden);
+    let mut dot = String::from("graph garden {\n");
+    for region in &garden.regions {
+        dot += &format!(
+            "    r{} [label=\"{} #{}\\narea {}\"];\n",
+            region.id,
+            region.plant,
+            region.id,
+            region.area()
+        );
     }
-    for (plant_type, region) in regions.iter() {
-        for (region_number, (garden_plots, _)) in region {
-            for (i, j) in garden_plots {
-                new_grid[i + 1][j + 1] = (*plant_type, *region_number);
-            }
-        }
+    for ((a, b), segments) in adjacency(garden) {
+        let style = if enclosed.get(&a) == Some(&b) || enclosed.get(&b) == Some(&a) {
+            ", style=bold"
+        } else {
+            ""
+        };
+        dot += &format!("    r{a} -- r{b} [label=\"{segments}\"{style}];\n");
     }
-    for i in 0..new_grid.len() - 1 {
-        for j in 0..new_grid[0].len() - 1 {
-            let window: Vec<(char, usize)> = vec![
-                new_grid[i][j],
-                new_grid[i][j + 1],
-                new_grid[i + 1][j],
-                new_grid[i + 1][j + 1],
-            ];
-            let unique_types = window.iter().collect::<HashSet<&(char, usize)>>();
-            for t in unique_types.iter() {
-                let count = window.iter().filter(|x| x == t).count();
-                if count == 1 {
-                    *corner_count.entry(**t).or_insert(0) += 1;
-                } else if count == 3 {
-                    *corner_count.entry(**t).or_insert(0) += 1;
-                } else if count == 2 {
-                    if (window[0] == window[3]) | (window[1] == window[2]) {
-                        *corner_count.entry(**t).or_insert(0) += 2;
-                    }
-                }
-            }
-        }
+    dot + "}\n"
+}
+
+/// Prints the region adjacency graph of the map as DOT.
+pub fn region_graph(input_uri: &str) -> String {
+    let input = read_data_from_file(input_uri);
+    let garden = find_regions(&input_to_grid::<char>(&input));
+    let dot = to_dot(&garden);
+    println!("{dot}");
+    dot
+}
+
+const PRICE_HEADER: [&str; 7] = [
+    "plant",
+    "id",
+    "area",
+    "perimeter",
+    "sides",
+    "part_1_price",
+    "part_2_price",
+];
+
+/// One row per region, then a row of totals with no plant or id.
+fn price_rows(garden: &Garden) -> Vec<[String; 7]> {
+    let mut rows: Vec<[String; 7]> = garden
+        .regions
+        .iter()
+        .map(|r| {
+            [
+                r.plant.to_string(),
+                r.id.to_string(),
+                r.area().to_string(),
+                r.perimeter.to_string(),
+                r.sides.to_string(),
+                r.fence_price().to_string(),
+                r.bulk_price().to_string(),
+            ]
+        })
+        .collect();
+    let total = |f: fn(&Region) -> usize| garden.regions.iter().map(f).sum::<usize>().to_string();
+    rows.push([
+        "total".to_string(),
+        String::new(),
+        total(Region::area),
+        total(|r| r.perimeter),
+        total(|r| r.sides),
+        total(Region::fence_price),
+        total(Region::bulk_price),
+    ]);
+    rows
+}
+
+/// The price report with columns padded to line up, numbers on the right.
+fn price_table(garden: &Garden) -> String {
+    let rows = price_rows(garden);
+    let widths: Vec<usize> = (0..PRICE_HEADER.len())
+        .map(|c| {
+            rows.iter()
+                .map(|row| row[c].len())
+                .chain([PRICE_HEADER[c].len()])
+                .max()
+                .unwrap()
+        })
+        .collect();
+    let mut table = String::new();
+    let header: Vec<String> = PRICE_HEADER
+        .iter()
+        .zip(&widths)
+        .enumerate()
+        .map(|(c, (name, width))| match c {
+            0 => format!("{name:<width$}"),
+            _ => format!("{name:>width$}"),
+        })
+        .collect();
+    table += &header.join("  ");
+    table += "\n";
+    for row in &rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(c, (cell, width))| match c {
+                0 => format!("{cell:<width$}"),
+                _ => format!("{cell:>width$}"),
+            })
+            .collect();
+        table += &line.join("  ");
+        table += "\n";
     }
-    corner_count.remove(&('.', 0));
-    let mut total: usize = 0;
-    for ((plant_type, region_number), fence_count) in corner_count {
-        let region_area = regions
-            .get(&plant_type)
-            .unwrap()
-            .get(&region_number)
-            .unwrap()
-            .0
-            .len();
-        total += region_area * fence_count;
+    table
+}
+
+fn price_csv(garden: &Garden) -> String {
+    let mut csv = PRICE_HEADER.join(",") + "\n";
+    for row in price_rows(garden) {
+        csv += &row.join(",");
+        csv += "\n";
     }
+    csv
+}
 
-    total
+/// Prints the fence prices of every region, as CSV if `csv` is set.
+pub fn price_report(input_uri: &str, csv: bool) -> String {
+    let input = read_data_from_file(input_uri);
+    let garden = find_regions(&input_to_grid::<char>(&input));
+    let report = if csv {
+        price_csv(&garden)
+    } else {
+        price_table(&garden)
+    };
+    print!("{report}");
+    report
+}
+
+pub fn part_1(input_uri: &str) -> usize {
+    let input = read_data_from_file(input_uri);
+    let grid = input_to_grid::<char>(&input);
+    find_regions(&grid)
+        .regions
+        .iter()
+        .map(|region| region.fence_price())
+        .sum()
+}
+
+pub fn part_2(input_uri: &str) -> usize {
+    let input = read_data_from_file(input_uri);
+    let grid = input_to_grid::<char>(&input);
+    find_regions(&grid)
+        .regions
+        .iter()
+        .map(|region| region.bulk_price())
+        .sum()
 }
 
 #[cfg(test)]
@@ -192,4 +406,199 @@ mod tests {
         let result = part_2("data/day12/test3.txt");
         assert_eq!(result, 368);
     }
+
+    const SMALL: &str = "AAAA
+BBCD
+BBCC
+EEEC";
+
+    const NESTED: &str = "OOOOO
+OXOXO
+OOOOO
+OXOXO
+OOOOO";
+
+    const LARGE: &str = "RRRRIICCFF
+RRRRIICCCF
+VVRRRCCFFF
+VVRCCCJFFF
+VVVVCJJCFE
+VVIVCCJJEE
+VVIIICJJEE
+MIIIIIJJEE
+MIIISIJEEE
+MMMISSJEEE";
+
+    fn prices(input: &str) -> (usize, usize) {
+        let garden = find_regions(&input_to_grid::<char>(input));
+        let part_1 = garden.regions.iter().map(Region::fence_price).sum();
+        let part_2 = garden.regions.iter().map(Region::bulk_price).sum();
+        (part_1, part_2)
+    }
+
+    #[test]
+    fn test_find_regions() {
+        let garden = find_regions(&input_to_grid::<char>(SMALL));
+        let summary: Vec<(usize, char, usize, usize, usize)> = garden
+            .regions
+            .iter()
+            .map(|r| (r.id, r.plant, r.area(), r.perimeter, r.sides))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 'A', 4, 10, 4),
+                (1, 'B', 4, 8, 4),
+                (2, 'C', 4, 10, 8),
+                (3, 'D', 1, 4, 4),
+                (4, 'E', 3, 8, 4),
+            ]
+        );
+        assert_eq!(garden.regions[2].bounding_box, ((1, 2), (3, 3)));
+        assert_eq!(garden.labels[3], vec![4, 4, 4, 2]);
+
+        assert_eq!(prices(SMALL), (140, 80));
+        assert_eq!(prices(NESTED), (772, 436));
+        assert_eq!(prices(LARGE), (1930, 1206));
+        assert_eq!(prices("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE"), (692, 236));
+        assert_eq!(
+            prices("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA"),
+            (1184, 368)
+        );
+    }
+
+    #[test]
+    fn test_large_region() {
+        // Deep enough to overflow the stack with one call per cell
+        let size = 600;
+        let grid = vec![vec!['A'; size]; size];
+        let garden = find_regions(&grid);
+        assert_eq!(garden.regions.len(), 1);
+        let region = &garden.regions[0];
+        assert_eq!(region.area(), size * size);
+        assert_eq!(region.perimeter, 4 * size);
+        assert_eq!(region.sides, 4);
+        assert_eq!(region.bounding_box, ((0, 0), (size - 1, size - 1)));
+    }
+
+    const RINGS: &str = "AAAAA
+ABBBA
+ABCBA
+ABBBA
+AAAAA";
+
+    #[test]
+    fn test_adjacency() {
+        let garden = find_regions(&input_to_grid::<char>(SMALL));
+        let edges: Vec<((usize, usize), usize)> = adjacency(&garden).into_iter().collect();
+        assert_eq!(
+            edges,
+            vec![
+                ((0, 1), 2),
+                ((0, 2), 1),
+                ((0, 3), 1),
+                ((1, 2), 2),
+                ((1, 4), 2),
+                ((2, 3), 2),
+                ((2, 4), 2),
+            ]
+        );
+        // Every inner fence segment is shared by exactly two regions
+        let garden = find_regions(&input_to_grid::<char>(LARGE));
+        let perimeter: usize = garden.regions.iter().map(|r| r.perimeter).sum();
+        let shared: usize = adjacency(&garden).values().sum();
+        assert_eq!(perimeter, 2 * shared + 2 * (10 + 10));
+    }
+
+    #[test]
+    fn test_holes_and_enclosures() {
+        let garden = find_regions(&input_to_grid::<char>(NESTED));
+        let enclosed = enclosures(&garden);
+        assert_eq!(enclosed.len(), 4);
+        assert!(enclosed.values().all(|outer| *outer == 0));
+        assert_eq!(holes(&garden, 0).len(), 4);
+        assert!(adjacency(&garden).values().all(|segments| *segments == 4));
+
+        let garden = find_regions(&input_to_grid::<char>(RINGS));
+        assert_eq!(
+            holes(&garden, 0),
+            vec![Hole {
+                cells: vec![
+                    (1, 1),
+                    (1, 2),
+                    (2, 1),
+                    (1, 3),
+                    (2, 2),
+                    (3, 1),
+                    (2, 3),
+                    (3, 2),
+                    (3, 3)
+                ],
+                regions: vec![1, 2],
+            }]
+        );
+        assert_eq!(
+            holes(&garden, 1),
+            vec![Hole {
+                cells: vec![(2, 2)],
+                regions: vec![2],
+            }]
+        );
+        assert_eq!(enclosures(&garden), BTreeMap::from([(1, 0), (2, 1)]));
+
+        // Two regions sharing a hole are not enclosed by a single region
+        let garden = find_regions(&input_to_grid::<char>("AAAA\nABCA\nAAAA"));
+        assert_eq!(holes(&garden, 0)[0].regions, vec![1, 2]);
+        assert!(enclosures(&garden).is_empty());
+        // Touching the edge of the map is never enclosed
+        let garden = find_regions(&input_to_grid::<char>(SMALL));
+        assert!(enclosures(&garden).is_empty());
+        assert!(holes(&garden, 2).is_empty());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let garden = find_regions(&input_to_grid::<char>(RINGS));
+        assert_eq!(
+            to_dot(&garden),
+            "graph garden {
+    r0 [label=\"A #0\\narea 16\"];
+    r1 [label=\"B #1\\narea 8\"];
+    r2 [label=\"C #2\\narea 1\"];
+    r0 -- r1 [label=\"12\", style=bold];
+    r1 -- r2 [label=\"4\", style=bold];
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_price_report() {
+        let garden = find_regions(&input_to_grid::<char>(SMALL));
+        assert_eq!(
+            price_table(&garden),
+            "plant  id  area  perimeter  sides  part_1_price  part_2_price
+A       0     4         10      4            40            16
+B       1     4          8      4            32            16
+C       2     4         10      8            40            32
+D       3     1          4      4             4             4
+E       4     3          8      4            24            12
+total        16         40     24           140            80
+"
+        );
+        assert_eq!(
+            price_csv(&garden),
+            "plant,id,area,perimeter,sides,part_1_price,part_2_price
+A,0,4,10,4,40,16
+B,1,4,8,4,32,16
+C,2,4,10,8,40,32
+D,3,1,4,4,4,4
+E,4,3,8,4,24,12
+total,,16,40,24,140,80
+"
+        );
+        let garden = find_regions(&input_to_grid::<char>(LARGE));
+        let totals = price_csv(&garden).lines().last().unwrap().to_string();
+        assert!(totals.ends_with(",1930,1206"));
+    }
 }
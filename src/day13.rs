@@ -1,133 +1,534 @@
 use crate::util::read_data_from_file;
 use regex::Regex;
 
-const TOL: f64 = 40.0 - 39.999;
+/// Part 1 says no button needs more than 100 presses.
+const PART_1_MAX_PRESSES: i128 = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Button {
+    movement: (i128, i128),
+    cost: i128,
+    max_presses: Option<i128>,
+}
+
+impl Button {
+    /// Most presses worth trying with `remaining` distance left. Movements and
+    /// costs are non-negative, so a button that does not move is never pressed.
+    fn press_bound(&self, remaining: (i128, i128)) -> Option<i128> {
+        let mut bound = self.max_presses.unwrap_or(i128::MAX);
+        for (step, left) in [
+            (self.movement.0, remaining.0),
+            (self.movement.1, remaining.1),
+        ] {
+            if left < 0 {
+                return None;
+            }
+            if step > 0 {
+                bound = bound.min(left / step);
+            }
+        }
+        if self.movement == (0, 0) {
+            bound = 0;
+        }
+        Some(bound)
+    }
+
+    fn within_limit(&self, presses: i128) -> bool {
+        presses >= 0
+            && self
+                .max_presses
+                .is_none_or(|max_presses| presses <= max_presses)
+    }
+}
 
 #[derive(Debug)]
 struct Machine {
-    a: (f64, f64),
-    b: (f64, f64),
-    prize: (f64, f64),
+    buttons: Vec<Button>,
+    prize: (i128, i128),
 }
 
-impl Machine {
-    fn solve(&self) -> Option<Vec<f64>> {
-        let mut lines = vec![
-            vec![self.a.0, self.b.0, self.prize.0],
-            vec![self.a.1, self.b.1, self.prize.1],
-        ];
-        let height = lines.len();
-        let width = lines[0].len();
-        for i in 0..height {
-            for j in i + 1..height {
-                let factor = lines[j][i] / lines[i][i];
-                for k in 0..width {
-                    lines[j][k] = lines[j][k] - factor * lines[i][k];
-                }
-            }
+/// Returns (g, x, y) with `a * x + b * y == g == gcd(a, b)`.
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+/// Cheapest non-negative (a, b) with `a * p + b * q == n`, for p, q >= 0 and
+/// not both zero. Solutions are `a0 + k * q / g`, `b0 - k * p / g`, and the
+/// cost is linear in k, so the optimum sits at one end of the valid k range.
+fn solve_line(p: i128, q: i128, n: i128, a: &Button, b: &Button) -> Option<(i128, i128)> {
+    let (g, x, y) = extended_gcd(p, q);
+    if n % g != 0 {
+        return None;
+    }
+    let (a0, b0) = (x * (n / g), y * (n / g));
+    let (a_step, b_step) = (q / g, p / g);
+    let a_max = a.max_presses.unwrap_or(i128::MAX / 4);
+    let b_max = b.max_presses.unwrap_or(i128::MAX / 4);
+    // 0 <= a0 + k * a_step <= a_max and 0 <= b0 - k * b_step <= b_max
+    let (k_min, k_max) = match (a_step, b_step) {
+        (0, _) if !a.within_limit(a0) => return None,
+        (_, 0) if !b.within_limit(b0) => return None,
+        (0, _) => (-(b_max - b0).div_euclid(b_step), b0.div_euclid(b_step)),
+        (_, 0) => (-a0.div_euclid(a_step), (a_max - a0).div_euclid(a_step)),
+        _ => (
+            (-a0.div_euclid(a_step)).max(-(b_max - b0).div_euclid(b_step)),
+            b0.div_euclid(b_step).min((a_max - a0).div_euclid(a_step)),
+        ),
+    };
+    if k_min > k_max {
+        return None;
+    }
+    let k = if a.cost * a_step - b.cost * b_step >= 0 {
+        k_min
+    } else {
+        k_max
+    };
+    Some((a0 + k * a_step, b0 - k * b_step))
+}
+
+/// Exact solution for two buttons: Cramer's rule when they are independent,
+/// otherwise the prize must lie on their common line and the problem reduces
+/// to one axis with many solutions, of which the cheapest is picked.
+fn solve_pair(a: &Button, b: &Button, prize: (i128, i128)) -> Option<(i128, i128)> {
+    let determinant = a.movement.0 * b.movement.1 - a.movement.1 * b.movement.0;
+    if determinant != 0 {
+        let a_numerator = prize.0 * b.movement.1 - prize.1 * b.movement.0;
+        let b_numerator = a.movement.0 * prize.1 - a.movement.1 * prize.0;
+        if a_numerator % determinant != 0 || b_numerator % determinant != 0 {
+            return None;
         }
-        for i in (0..height).rev() {
-            for j in (0..i).rev() {
-                let factor = lines[j][i] / lines[i][i];
-                for k in 0..width {
-                    lines[j][k] = lines[j][k] - factor * lines[i][k];
-                }
-            }
+        let (a_presses, b_presses) = (a_numerator / determinant, b_numerator / determinant);
+        return (a.within_limit(a_presses) && b.within_limit(b_presses))
+            .then_some((a_presses, b_presses));
+    }
+
+    let direction = if a.movement != (0, 0) {
+        a.movement
+    } else {
+        b.movement
+    };
+    if direction == (0, 0) {
+        return (prize == (0, 0)).then_some((0, 0));
+    }
+    if direction.0 * prize.1 - direction.1 * prize.0 != 0 {
+        return None;
+    }
+    if direction.0 != 0 {
+        solve_line(a.movement.0, b.movement.0, prize.0, a, b)
+    } else {
+        solve_line(a.movement.1, b.movement.1, prize.1, a, b)
+    }
+}
+
+fn presses_cost(buttons: &[Button], presses: &[i128]) -> i128 {
+    buttons
+        .iter()
+        .zip(presses)
+        .map(|(button, presses)| button.cost * presses)
+        .sum()
+}
+
+/// Largest press count searched for a button that is not covered by the
+/// lattice shortcut below; machines needing more are treated as unsolvable.
+const MAX_SEARCH_PRESSES: i128 = 1_000_000;
+
+/// Presses `k` for which `min <= (c - k * m) / d <= max` over the reals, with
+/// `d > 0` and no upper limit when `max` is None.
+fn press_range(c: i128, m: i128, d: i128, min: i128, max: Option<i128>) -> Option<(i128, i128)> {
+    // d * min <= c - k * m <= d * max
+    let low = max.map(|max| c - d * max);
+    let high = c - d * min;
+    match m.signum() {
+        0 => (low.is_none_or(|low| low <= 0) && 0 <= high).then_some((i128::MIN, i128::MAX)),
+        1 => Some((
+            low.map_or(i128::MIN, |low| -((-low).div_euclid(m))),
+            high.div_euclid(m),
+        )),
+        _ => Some((
+            -(high.div_euclid(-m)),
+            low.map_or(i128::MAX, |low| (-low).div_euclid(-m)),
+        )),
+    }
+}
+
+/// Press counts of `first` worth trying when `a` and `b` are independent.
+/// Cramer's numerators for `a` and `b` are linear in the presses `k` of
+/// `first`, so they are integral on residue classes mod the determinant and
+/// non-negative on an interval. Cost is linear in `k` too, so only the ends
+/// of each residue class within that interval can be cheapest.
+fn lattice_presses(
+    first: &Button,
+    a: &Button,
+    b: &Button,
+    prize: (i128, i128),
+    bound: i128,
+) -> Vec<i128> {
+    let cross = |u: (i128, i128), v: (i128, i128)| u.0 * v.1 - u.1 * v.0;
+    let sign = cross(a.movement, b.movement).signum();
+    let d = sign * cross(a.movement, b.movement);
+    let (a_c, a_m) = (
+        sign * cross(prize, b.movement),
+        sign * cross(first.movement, b.movement),
+    );
+    let (b_c, b_m) = (
+        sign * cross(a.movement, prize),
+        sign * cross(a.movement, first.movement),
+    );
+    let (Some(a_range), Some(b_range)) = (
+        press_range(a_c, a_m, d, 0, a.max_presses),
+        press_range(b_c, b_m, d, 0, b.max_presses),
+    ) else {
+        return Vec::new();
+    };
+    let low = a_range.0.max(b_range.0).max(0);
+    let high = a_range.1.min(b_range.1).min(bound);
+    let mut presses = Vec::new();
+    if low > high {
+        return presses;
+    }
+    for residue in 0..d.min(bound + 1) {
+        if (a_c - residue * a_m) % d != 0 || (b_c - residue * b_m) % d != 0 {
+            continue;
         }
-        let mut result: Vec<f64> = Vec::new();
-        for i in 0..height {
-            let x = lines[i][width - 1] / lines[i][i];
-            if (x - x.round()).abs() <= TOL {
-                result.push(x.round());
-            } else {
-                dbg!((x - x.round()).abs());
-                return None;
+        let first_k = residue + (low - residue + d - 1).div_euclid(d) * d;
+        let last_k = residue + (high - residue).div_euclid(d) * d;
+        if first_k <= last_k {
+            presses.extend([first_k, last_k]);
+        }
+    }
+    presses
+}
+
+/// Cheapest presses of `buttons` reaching `prize`. The last two buttons are
+/// solved exactly, a third before them through `lattice_presses` when they
+/// are independent, and any others by searching their bounded press counts.
+fn solve_buttons(buttons: &[Button], prize: (i128, i128)) -> Option<Vec<i128>> {
+    match buttons {
+        [] => (prize == (0, 0)).then_some(Vec::new()),
+        [button] => {
+            let zero = Button {
+                movement: (0, 0),
+                cost: 0,
+                max_presses: Some(0),
+            };
+            let (presses, _) = solve_pair(button, &zero, prize)?;
+            Some(vec![presses])
+        }
+        [a, b] => {
+            let (a_presses, b_presses) = solve_pair(a, b, prize)?;
+            Some(vec![a_presses, b_presses])
+        }
+        [first, rest @ ..] => {
+            let bound = first.press_bound(prize)?;
+            let candidates: Vec<i128> = match rest {
+                [a, b] if a.movement.0 * b.movement.1 != a.movement.1 * b.movement.0 => {
+                    lattice_presses(first, a, b, prize, bound)
+                }
+                _ if bound > MAX_SEARCH_PRESSES => return None,
+                _ => (0..=bound).collect(),
+            };
+            let mut best: Option<Vec<i128>> = None;
+            for presses in candidates {
+                let remaining = (
+                    prize.0 - presses * first.movement.0,
+                    prize.1 - presses * first.movement.1,
+                );
+                if let Some(rest_presses) = solve_buttons(rest, remaining) {
+                    let mut candidate = vec![presses];
+                    candidate.extend(rest_presses);
+                    if best.as_ref().is_none_or(|best| {
+                        presses_cost(buttons, &candidate) < presses_cost(buttons, best)
+                    }) {
+                        best = Some(candidate);
+                    }
+                }
             }
+            best
         }
-        Some(result)
     }
 }
 
-fn process_input(input: &str, prize_mod: f64) -> Vec<Machine> {
+impl Machine {
+    /// Presses per button for the cheapest way to reach the prize, if any.
+    fn solve(&self) -> Option<Vec<u64>> {
+        let presses = solve_buttons(&self.buttons, self.prize)?;
+        Some(presses.iter().map(|presses| *presses as u64).collect())
+    }
+
+    fn cost(&self) -> Option<u64> {
+        let presses = self.solve()?;
+        let cost = self
+            .buttons
+            .iter()
+            .zip(presses)
+            .map(|(button, presses)| button.cost as u64 * presses)
+            .sum();
+        Some(cost)
+    }
+}
+
+/// Buttons look like `Button A: X+94, Y+34`, optionally followed by
+/// `, Cost=2` and `, Max=50`. Without a cost, A costs 3 and others cost 1.
+fn process_input(input: &str, prize_mod: i128, max_presses: Option<i128>) -> Vec<Machine> {
     let mut machines: Vec<Machine> = Vec::new();
-    let mut a: (f64, f64) = (0.0, 0.0);
-    let mut b: (f64, f64) = (0.0, 0.0);
-    let mut prize: (f64, f64) = (0.0, 0.0);
-    let a_pattern = Regex::new(r"A: X\+(\d+), Y\+(\d+)").unwrap();
-    let b_pattern = Regex::new(r"B: X\+(\d+), Y\+(\d+)").unwrap();
+    let mut buttons: Vec<Button> = Vec::new();
+    let mut prize: (i128, i128) = (0, 0);
+    let button_pattern =
+        Regex::new(r"Button (\w+): X\+(\d+), Y\+(\d+)(?:, Cost=(\d+))?(?:, Max=(\d+))?").unwrap();
     let prize_pattern = Regex::new(r"X\=(\d+), Y\=(\d+)").unwrap();
 
     for line in input.lines() {
-        for (_, [x, y]) in a_pattern.captures_iter(line).map(|c| c.extract()) {
-            a = (x.parse::<f64>().unwrap(), y.parse::<f64>().unwrap())
-        }
-        for (_, [x, y]) in b_pattern.captures_iter(line).map(|c| c.extract()) {
-            b = (x.parse::<f64>().unwrap(), y.parse::<f64>().unwrap())
+        for captures in button_pattern.captures_iter(line) {
+            let parse = |i: usize| captures.get(i).map(|m| m.as_str().parse::<i128>().unwrap());
+            let default_cost = if &captures[1] == "A" { 3 } else { 1 };
+            buttons.push(Button {
+                movement: (parse(2).unwrap(), parse(3).unwrap()),
+                cost: parse(4).unwrap_or(default_cost),
+                max_presses: parse(5).or(max_presses),
+            });
         }
         for (_, [x, y]) in prize_pattern.captures_iter(line).map(|c| c.extract()) {
             prize = (
-                x.parse::<f64>().unwrap() + prize_mod,
-                y.parse::<f64>().unwrap() + prize_mod,
+                x.parse::<i128>().unwrap() + prize_mod,
+                y.parse::<i128>().unwrap() + prize_mod,
             )
         }
 
-        if line.is_empty() {
-            let machine = Machine { a, b, prize };
+        if line.is_empty() && !buttons.is_empty() {
+            let machine = Machine {
+                buttons: std::mem::take(&mut buttons),
+                prize,
+            };
             machines.push(machine);
         }
     }
-    let machine = Machine { a, b, prize };
-    machines.push(machine);
+    if !buttons.is_empty() {
+        machines.push(Machine { buttons, prize });
+    }
     machines
 }
 
-pub fn part_1(input_uri: &str) -> f64 {
+fn total_cost(machines: &[Machine]) -> u64 {
+    machines.iter().filter_map(|machine| machine.cost()).sum()
+}
+
+pub fn part_1(input_uri: &str) -> u64 {
     let input = read_data_from_file(input_uri);
-    let machines = process_input(&input, 0.0);
-    let mut total_cost: f64 = 0.0;
-    for machine in machines {
-        match machine.solve() {
-            Some(x) => {
-                total_cost += x[0] * 3.0;
-                total_cost += x[1] * 1.0;
-            }
-            None => {}
-        }
-    }
-    total_cost
+    let machines = process_input(&input, 0, Some(PART_1_MAX_PRESSES));
+    total_cost(&machines)
 }
 
-pub fn part_2(input_uri: &str) -> f64 {
+pub fn part_2(input_uri: &str) -> u64 {
     let input = read_data_from_file(input_uri);
-    let machines = process_input(&input, 10000000000000.0);
-    let mut total_cost: f64 = 0.0;
-    for machine in machines {
-        match machine.solve() {
-            Some(x) => {
-                total_cost += x[0] * 3.0;
-                total_cost += x[1] * 1.0;
-            }
-            None => {
-                println!("No solution")
-            }
-        }
-    }
-    total_cost
+    let machines = process_input(&input, 10000000000000, None);
+    total_cost(&machines)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::XorShift;
+
+    const EXAMPLE: &str = "Button A: X+94, Y+34
+Button B: X+22, Y+67
+Prize: X=8400, Y=5400
+
+Button A: X+26, Y+66
+Button B: X+67, Y+21
+Prize: X=12748, Y=12176
+
+Button A: X+17, Y+86
+Button B: X+84, Y+37
+Prize: X=7870, Y=6450
+
+Button A: X+69, Y+23
+Button B: X+27, Y+71
+Prize: X=18641, Y=10279";
+
+    #[test]
+    fn test_solve() {
+        let machines = process_input(EXAMPLE, 0, Some(PART_1_MAX_PRESSES));
+        let solutions: Vec<Option<Vec<u64>>> = machines.iter().map(|m| m.solve()).collect();
+        assert_eq!(
+            solutions,
+            vec![Some(vec![80, 40]), None, Some(vec![38, 86]), None]
+        );
+        assert_eq!(total_cost(&machines), 480);
+
+        let machines = process_input(EXAMPLE, 10000000000000, None);
+        let solutions: Vec<bool> = machines.iter().map(|m| m.solve().is_some()).collect();
+        assert_eq!(solutions, vec![false, true, false, true]);
+        assert_eq!(total_cost(&machines), 875318608908);
+    }
+
+    fn button(movement: (i128, i128), cost: i128, max_presses: Option<i128>) -> Button {
+        Button {
+            movement,
+            cost,
+            max_presses,
+        }
+    }
+
+    fn machine(a: (i128, i128), b: (i128, i128), prize: (i128, i128)) -> Machine {
+        Machine {
+            buttons: vec![button(a, 3, None), button(b, 1, None)],
+            prize,
+        }
+    }
+
+    #[test]
+    fn test_solve_collinear() {
+        // B is cheaper per unit of distance, so use as many B presses as fit
+        assert_eq!(machine((2, 2), (1, 1), (7, 7)).solve(), Some(vec![0, 7]));
+        // A moves 6 per press for 3 tokens, B only 1 per token, so prefer A
+        assert_eq!(machine((6, 12), (1, 2), (20, 40)).solve(), Some(vec![3, 2]));
+        // Only one mix of a 4-step and 6-step button hits 10
+        assert_eq!(machine((4, 2), (6, 3), (10, 5)).solve(), Some(vec![1, 1]));
+        // Off the line, or not reachable by any integer mix
+        assert_eq!(machine((2, 2), (1, 1), (7, 8)).solve(), None);
+        assert_eq!(machine((4, 2), (6, 3), (11, 5)).solve(), None);
+        assert_eq!(machine((4, 2), (6, 3), (2, 1)).solve(), None);
+        // Movement only along y
+        assert_eq!(machine((0, 3), (0, 5), (0, 19)).solve(), Some(vec![3, 2]));
+        // Degenerate buttons
+        assert_eq!(machine((0, 0), (0, 0), (0, 0)).solve(), Some(vec![0, 0]));
+        assert_eq!(machine((0, 0), (0, 0), (1, 0)).solve(), None);
+        assert_eq!(machine((0, 0), (3, 1), (9, 3)).solve(), Some(vec![0, 3]));
+        assert_eq!(machine((3, 1), (3, 1), (9, 3)).solve(), Some(vec![0, 3]));
+    }
+
+    #[test]
+    fn test_press_limits() {
+        // The cheap solution needs 101 presses of B, so the cap forces A
+        let capped = |max_presses| Machine {
+            buttons: vec![
+                button((1, 1), 3, max_presses),
+                button((1, 1), 1, max_presses),
+            ],
+            prize: (101, 101),
+        };
+        assert_eq!(capped(None).solve(), Some(vec![0, 101]));
+        assert_eq!(capped(Some(100)).solve(), Some(vec![1, 100]));
+        assert_eq!(capped(Some(50)).solve(), None);
+
+        let machines = process_input(
+            "Button A: X+1, Y+1\nButton B: X+1, Y+1, Max=100\nPrize: X=101, Y=101",
+            0,
+            None,
+        );
+        assert_eq!(machines[0].solve(), Some(vec![1, 100]));
+        let machines = process_input(EXAMPLE, 0, Some(79));
+        assert_eq!(machines[0].solve(), None);
+    }
+
+    #[test]
+    fn test_many_buttons() {
+        let input = "Button A: X+94, Y+34
+Button B: X+22, Y+67
+Button C: X+10, Y+10, Cost=1, Max=20
+Prize: X=8500, Y=5500
+
+Button A: X+3, Y+0
+Button B: X+0, Y+2, Cost=2
+Button C: X+1, Y+1, Cost=3
+Prize: X=5, Y=4";
+        let machines = process_input(input, 0, Some(PART_1_MAX_PRESSES));
+        assert_eq!(machines[0].buttons.len(), 3);
+        assert_eq!(machines[0].buttons[2], button((10, 10), 1, Some(20)));
+        assert_eq!(machines[0].solve(), Some(vec![80, 40, 10]));
+        assert_eq!(machines[0].cost(), Some(290));
+        // 1 A + 2 C + 1 B (3 + 6 + 2 = 11) beats any other mix
+        assert_eq!(machines[1].solve(), Some(vec![1, 1, 2]));
+        assert_eq!(machines[1].cost(), Some(11));
+
+        let single = Machine {
+            buttons: vec![button((4, 6), 2, None)],
+            prize: (20, 30),
+        };
+        assert_eq!(single.solve(), Some(vec![5]));
+        assert_eq!(single.cost(), Some(10));
+    }
+
+    /// Every press mix within `max` presses per button, cheapest first.
+    fn brute_force(buttons: &[Button], prize: (i128, i128), max: i128) -> Option<i128> {
+        let mut best: Option<i128> = None;
+        for a in 0..=max {
+            for b in 0..=max {
+                for c in 0..=max {
+                    let presses = [a, b, c];
+                    let reached = buttons.iter().zip(presses).fold((0, 0), |at, (button, n)| {
+                        (at.0 + n * button.movement.0, at.1 + n * button.movement.1)
+                    });
+                    if reached == prize {
+                        let cost = presses_cost(buttons, &presses);
+                        best = Some(best.map_or(cost, |best| best.min(cost)));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn test_lattice_presses() {
+        let mut rng = XorShift(13);
+        for _ in 0..300 {
+            let buttons: Vec<Button> = (0..3)
+                .map(|_| {
+                    button(
+                        (rng.below(6) as i128, rng.below(6) as i128),
+                        rng.below(4) as i128 + 1,
+                        Some(12),
+                    )
+                })
+                .collect();
+            let presses = [rng.below(13), rng.below(13), rng.below(13)].map(|n| n as i128);
+            let prize = buttons.iter().zip(presses).fold((0, 0), |at, (button, n)| {
+                (at.0 + n * button.movement.0, at.1 + n * button.movement.1)
+            });
+            let solved =
+                solve_buttons(&buttons, prize).map(|presses| presses_cost(&buttons, &presses));
+            assert_eq!(
+                solved,
+                brute_force(&buttons, prize, 12),
+                "{buttons:?} {prize:?}"
+            );
+        }
+
+        // Without the lattice this would search about 10^12 presses of C
+        let input = "Button A: X+94, Y+34
+Button B: X+22, Y+67
+Button C: X+7, Y+5, Cost=2
+Prize: X=8400, Y=5400";
+        let machines = process_input(input, 10000000000000, None);
+        let presses = machines[0].solve().unwrap();
+        let reached = machines[0]
+            .buttons
+            .iter()
+            .zip(&presses)
+            .fold((0, 0), |at, (button, n)| {
+                (
+                    at.0 + *n as i128 * button.movement.0,
+                    at.1 + *n as i128 * button.movement.1,
+                )
+            });
+        assert_eq!(reached, machines[0].prize);
+    }
 
     #[test]
     fn test_part_1() {
         let result = part_1("data/day13/test.txt");
-        assert_eq!(result, 480.0);
+        assert_eq!(result, 480);
     }
 
     #[test]
     fn test_part_2() {
         let result = part_2("data/day13/test.txt");
-        dbg!(result);
+        assert_eq!(result, 875318608908);
     }
 }
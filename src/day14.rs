@@ -1,6 +1,3 @@
-use std::collections::HashMap;
-use std::io;
-
 use crate::util::read_data_from_file;
 use regex::Regex;
 
@@ -58,23 +55,74 @@ fn process_input(input: &str) -> Vec<Robot> {
     robots
 }
 
-fn count(robots: &mut Vec<Robot>, grid_height: usize, grid_width: usize) -> usize {
-    let mut quadrant_count: HashMap<(isize, isize), usize> = HashMap::new();
-    let half_grid_width = (grid_width / 2) as isize;
-    let half_grid_height = (grid_height / 2) as isize;
+/// The room in the real puzzle, `(width, height)`.
+const PUZZLE_ROOM: (usize, usize) = (101, 103);
+
+/// `(width, height)` from a `room=101x103` header line, if there is one.
+fn room_header(input: &str) -> Option<(usize, usize)> {
+    let header = Regex::new(r"(?m)^room=(\d+)x(\d+)$").unwrap();
+    let captures = header.captures(input)?;
+    let grid_width = captures[1]
+        .parse::<usize>()
+        .expect("Can't convert to usize");
+    let grid_height = captures[2]
+        .parse::<usize>()
+        .expect("Can't convert to usize");
+    Some((grid_width, grid_height))
+}
+
+/// Room size from the header, otherwise the puzzle's 101x103.
+fn room_size(input: &str) -> (usize, usize) {
+    room_header(input).unwrap_or(PUZZLE_ROOM)
+}
+
+/// Room size from the header, otherwise the smallest room that fits every
+/// robot's starting position.
+fn room_size_inferred(input: &str, robots: &[Robot]) -> (usize, usize) {
+    if let Some(room) = room_header(input) {
+        return room;
+    }
+    let grid_width = robots.iter().map(|robot| robot.x + 1).max().unwrap_or(0);
+    let grid_height = robots.iter().map(|robot| robot.y + 1).max().unwrap_or(0);
+    (grid_width as usize, grid_height as usize)
+}
+
+/// The room for the file as guessed by `room_size_inferred`, to pass as
+/// `room` when the input has no header and is not the puzzle's room.
+pub fn inferred_room(input_uri: &str) -> (usize, usize) {
+    let input = read_data_from_file(input_uri);
+    room_size_inferred(&input, &process_input(&input))
+}
+
+/// Robots per quadrant, ordered top-left, top-right, bottom-left,
+/// bottom-right. Odd-sized rooms have a middle row and column that belong to
+/// no quadrant; even-sized rooms split evenly.
+fn quadrant_counts(robots: &[Robot], grid_height: usize, grid_width: usize) -> [usize; 4] {
+    let quadrant = |position: isize, size: usize| {
+        if position < (size / 2) as isize {
+            Some(0)
+        } else if position >= size.div_ceil(2) as isize {
+            Some(1)
+        } else {
+            None
+        }
+    };
+    let mut quadrant_count = [0usize; 4];
     for robot in robots.iter() {
-        if (robot.x == half_grid_width) | (robot.y == half_grid_height) {
-            continue;
+        if let (Some(quad_x), Some(quad_y)) = (
+            quadrant(robot.x, grid_width),
+            quadrant(robot.y, grid_height),
+        ) {
+            quadrant_count[quad_y * 2 + quad_x] += 1;
         }
-        let quad_x = robot.x / (half_grid_width + 1);
-        let quad_y = robot.y / (half_grid_height + 1);
-        *quadrant_count.entry((quad_x, quad_y)).or_insert(0) += 1;
     }
-    let mut safety_factor = 1;
-    for (_, count) in quadrant_count {
-        safety_factor *= count;
-    }
-    safety_factor
+    quadrant_count
+}
+
+fn count(robots: &[Robot], grid_height: usize, grid_width: usize) -> usize {
+    quadrant_counts(robots, grid_height, grid_width)
+        .iter()
+        .product()
 }
 
 fn step_and_count(
@@ -90,77 +138,229 @@ fn step_and_count(
     count(robots, grid_height, grid_width)
 }
 
-pub fn part_1(input_uri: &str) -> usize {
+/// Safety factor after `n_steps`. `room` is `(width, height)`; when `None` it
+/// comes from the header, or is the puzzle's 101x103, see `room_size`.
+pub fn part_1_with(input_uri: &str, room: Option<(usize, usize)>, n_steps: usize) -> usize {
     let input = read_data_from_file(input_uri);
     let mut robots = process_input(&input);
-    let grid_height: usize = 103;
-    let grid_width: usize = 101;
-    let n_steps = 100;
-    let safety_factor = step_and_count(&mut robots, n_steps, grid_height, grid_width);
-    safety_factor
-}
-
-fn grid(robots: &Vec<Robot>, grid_height: usize, grid_width: usize) -> Vec<Vec<char>> {
-    let mut grid: Vec<Vec<char>> = Vec::new();
-    for i in 0..grid_width + 2 {
-        grid.push(Vec::new());
-        for _ in 0..grid_height + 2 {
-            grid[i].push(' ')
-        }
+    let (grid_width, grid_height) = room.unwrap_or_else(|| room_size(&input));
+    step_and_count(&mut robots, n_steps, grid_height, grid_width)
+}
+
+pub fn part_1(input_uri: &str) -> usize {
+    part_1_with(input_uri, None, 100)
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
-    for robot in robots {
-        grid[robot.y as usize][robot.x as usize] = '▃';
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
+
+impl Robot {
+    /// Steps until the robot is back where it started.
+    fn period(&self, grid_width: usize, grid_height: usize) -> usize {
+        let vx = self.vx.rem_euclid(grid_width as isize) as usize;
+        let vy = self.vy.rem_euclid(grid_height as isize) as usize;
+        lcm(
+            grid_width / gcd(vx, grid_width),
+            grid_height / gcd(vy, grid_height),
+        )
     }
-    grid
 }
 
-fn check_line(robots: &Vec<Robot>, grid_height: usize, grid_width: usize) -> bool {
-    let grid = grid(robots, grid_height, grid_width);
+/// Occupancy statistics over the frames at steps `0..n_steps`.
+#[derive(Debug)]
+pub struct RobotAnalysis {
+    /// Robot visits per tile, indexed `[y][x]`.
+    pub heatmap: Vec<Vec<usize>>,
+    /// `quadrant_counts` for every frame.
+    pub quadrant_counts: Vec<[usize; 4]>,
+    /// Most robots on a single tile in every frame.
+    pub max_stacked: Vec<usize>,
+    /// Steps until the whole swarm repeats.
+    pub period: usize,
+}
+
+const SHADES: [char; 10] = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+impl RobotAnalysis {
+    pub fn heatmap_csv(&self) -> String {
+        self.heatmap
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|visits| visits.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",")
+            })
+            .map(|line| line + "\n")
+            .collect()
+    }
 
-    for line in grid {
-        let line_count = line
+    pub fn steps_csv(&self) -> String {
+        let mut csv =
+            String::from("step,top_left,top_right,bottom_left,bottom_right,max_stacked\n");
+        for (step, (quadrants, max_stacked)) in self
+            .quadrant_counts
             .iter()
-            .map(|x| if *x == '▃' { 1 } else { 0 })
-            .sum::<usize>();
-        if line_count >= 30 {
-            return true;
+            .zip(&self.max_stacked)
+            .enumerate()
+        {
+            let [top_left, top_right, bottom_left, bottom_right] = quadrants;
+            csv += &format!(
+                "{step},{top_left},{top_right},{bottom_left},{bottom_right},{max_stacked}\n"
+            );
         }
+        csv
     }
-    return false;
-}
 
-fn display_grid(robots: &Vec<Robot>, grid_height: usize, grid_width: usize) {
-    let grid = grid(robots, grid_height, grid_width);
+    /// Heatmap as text, darker characters for busier tiles.
+    pub fn render_heatmap(&self) -> String {
+        let max_visits = self.heatmap.iter().flatten().max().copied().unwrap_or(0);
+        self.heatmap
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|visits| match visits {
+                        0 => SHADES[0],
+                        _ => {
+                            SHADES[1 + (visits - 1) * (SHADES.len() - 2) / (max_visits - 1).max(1)]
+                        }
+                    })
+                    .chain(['\n'])
+                    .collect::<String>()
+            })
+            .collect()
+    }
+}
 
-    for line in grid {
-        let s: String = line.into_iter().collect();
-        println!("{s}");
+fn analyse_robots(
+    robots: &mut [Robot],
+    n_steps: usize,
+    grid_height: usize,
+    grid_width: usize,
+) -> RobotAnalysis {
+    let mut analysis = RobotAnalysis {
+        heatmap: vec![vec![0; grid_width]; grid_height],
+        quadrant_counts: Vec::new(),
+        max_stacked: Vec::new(),
+        period: robots
+            .iter()
+            .map(|robot| robot.period(grid_width, grid_height))
+            .fold(1, lcm),
+    };
+    for _ in 0..n_steps {
+        let mut frame = vec![vec![0; grid_width]; grid_height];
+        for robot in robots.iter() {
+            frame[robot.y as usize][robot.x as usize] += 1;
+            analysis.heatmap[robot.y as usize][robot.x as usize] += 1;
+        }
+        let max_stacked = frame.iter().flatten().max().copied().unwrap_or(0);
+        analysis.max_stacked.push(max_stacked);
+        analysis
+            .quadrant_counts
+            .push(quadrant_counts(robots, grid_height, grid_width));
+        for robot in robots.iter_mut() {
+            robot.non_blocking_steps(1, grid_width, grid_height);
+        }
     }
+    analysis
 }
 
-pub fn part_2(input_uri: &str) {
+/// Simulates `n_steps` frames and collects occupancy statistics. `room` is
+/// `(width, height)`; when `None` it comes from the header, or is the puzzle's
+/// 101x103, see `room_size`.
+pub fn analyse(input_uri: &str, room: Option<(usize, usize)>, n_steps: usize) -> RobotAnalysis {
     let input = read_data_from_file(input_uri);
     let mut robots = process_input(&input);
-    let grid_height: usize = 103;
-    let grid_width: usize = 101;
-    let mut user_str = String::new();
-    let mut n_steps: usize = 0;
-    loop {
-        if check_line(&robots, grid_height, grid_width) {
-            println!("{n_steps}");
-            display_grid(&robots, grid_height, grid_width);
-            io::stdin()
-                .read_line(&mut user_str)
-                .expect("Failed to read line");
-        }
-        step_and_count(&mut robots, 1, grid_height, grid_width);
-        n_steps += 1;
+    let (grid_width, grid_height) = room.unwrap_or_else(|| room_size(&input));
+    analyse_robots(&mut robots, n_steps, grid_height, grid_width)
+}
+
+/// How far below the average variance an axis must drop before the frame is
+/// taken to be the picture rather than noise.
+const PICTURE_VARIANCE_RATIO: f64 = 0.6;
+
+fn variance(values: &[isize]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<isize>() as f64 / n;
+    values
+        .iter()
+        .map(|value| (*value as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n
+}
+
+/// Step in `0..period` where the chosen coordinate is least spread out, if it
+/// stands out from the rest. A robot's x repeats every `grid_width` steps and
+/// its y every `grid_height` steps, so each axis only needs one period.
+fn tightest_step(
+    robots: &[Robot],
+    period: usize,
+    grid_height: usize,
+    grid_width: usize,
+    axis: fn((isize, isize)) -> isize,
+) -> Option<usize> {
+    let mut variances: Vec<f64> = Vec::new();
+    for n_steps in 0..period {
+        let values: Vec<isize> = robots
+            .iter()
+            .map(|robot| {
+                axis(wrapped_next_location(
+                    robot.x,
+                    robot.y,
+                    robot.vx * n_steps as isize,
+                    robot.vy * n_steps as isize,
+                    grid_width,
+                    grid_height,
+                ))
+            })
+            .collect();
+        variances.push(variance(&values));
+    }
+    let mean = variances.iter().sum::<f64>() / variances.len() as f64;
+    let (step, min) = variances
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))?;
+    if *min < mean * PICTURE_VARIANCE_RATIO {
+        Some(step)
+    } else {
+        None
+    }
+}
+
+/// First step at which the robots draw the picture: the step whose x spread
+/// is tightest modulo the width and whose y spread is tightest modulo the
+/// height, combined as in the Chinese remainder theorem.
+fn find_picture(robots: &[Robot], grid_height: usize, grid_width: usize) -> Option<usize> {
+    if robots.is_empty() {
+        return None;
     }
+    let x_step = tightest_step(robots, grid_width, grid_height, grid_width, |(x, _)| x)?;
+    let y_step = tightest_step(robots, grid_height, grid_height, grid_width, |(_, y)| y)?;
+    (0..grid_width * grid_height)
+        .find(|n_steps| n_steps % grid_width == x_step && n_steps % grid_height == y_step)
+}
+
+pub fn part_2(input_uri: &str) -> Option<usize> {
+    let input = read_data_from_file(input_uri);
+    let robots = process_input(&input);
+    let (grid_width, grid_height) = room_size(&input);
+    find_picture(&robots, grid_height, grid_width)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::XorShift;
 
     #[test]
     fn test_wrapped_next_location() {
@@ -174,12 +374,128 @@ mod tests {
 
     #[test]
     fn test_step_and_count() {
-        let input = read_data_from_file("data/day14/test.txt");
-        let mut robots = process_input(&input);
-        let grid_height: usize = 7;
-        let grid_width: usize = 11;
-        let n_steps = 100;
-        let safety_factor = step_and_count(&mut robots, n_steps, grid_height, grid_width);
+        let safety_factor = part_1_with("data/day14/test.txt", Some((11, 7)), 100);
         assert_eq!(safety_factor, 12);
     }
+
+    const EXAMPLE: &str = "p=0,4 v=3,-3
+p=6,3 v=-1,-3
+p=10,3 v=-1,2
+p=2,0 v=2,-1
+p=0,0 v=1,3
+p=3,0 v=-2,-2
+p=7,6 v=-1,-3
+p=3,0 v=-1,-2
+p=9,3 v=2,3
+p=7,3 v=-1,2
+p=2,4 v=2,-3
+p=9,5 v=-3,-3";
+
+    #[test]
+    fn test_room_size() {
+        let robots = process_input(EXAMPLE);
+        // Without a header the puzzle's room is assumed unless asked to infer
+        assert_eq!(room_size(EXAMPLE), (101, 103));
+        assert_eq!(room_size_inferred(EXAMPLE, &robots), (11, 7));
+        let input = format!("room=11x7\n{EXAMPLE}");
+        assert_eq!(room_size(&input), (11, 7));
+        let input = format!("room=20x30\n{EXAMPLE}");
+        assert_eq!(room_size_inferred(&input, &robots), (20, 30));
+    }
+
+    #[test]
+    fn test_count_even_room() {
+        // In a 4x4 room every tile is in a quadrant
+        let mut robots: Vec<Robot> = [(0, 0), (1, 1), (2, 0), (3, 3), (1, 2), (2, 2)]
+            .into_iter()
+            .map(|(x, y)| Robot { x, y, vx: 0, vy: 0 })
+            .collect();
+        assert_eq!(count(&robots, 4, 4), 4);
+        // An empty quadrant makes the room safe
+        robots.retain(|robot| robot.x < 2);
+        assert_eq!(count(&robots, 4, 4), 0);
+        // 5x5 has a middle row and column that do not count
+        let robots: Vec<Robot> = [(0, 0), (2, 1), (4, 0), (0, 4), (4, 4), (1, 2)]
+            .into_iter()
+            .map(|(x, y)| Robot { x, y, vx: 0, vy: 0 })
+            .collect();
+        assert_eq!(count(&robots, 5, 5), 1);
+    }
+
+    #[test]
+    fn test_analyse_robots() {
+        let mut robots = vec![
+            Robot {
+                x: 0,
+                y: 0,
+                vx: 1,
+                vy: 0,
+            },
+            Robot {
+                x: 2,
+                y: 1,
+                vx: -1,
+                vy: 1,
+            },
+        ];
+        let analysis = analyse_robots(&mut robots, 3, 2, 3);
+        // Periods 3 and lcm(3, 2) = 6
+        assert_eq!(analysis.period, 6);
+        assert_eq!(analysis.heatmap, vec![vec![1, 2, 1], vec![1, 0, 1]]);
+        assert_eq!(analysis.max_stacked, vec![1, 2, 1]);
+        assert_eq!(
+            analysis.quadrant_counts,
+            vec![[1, 0, 0, 1], [0, 0, 0, 0], [0, 1, 1, 0]]
+        );
+        assert_eq!(analysis.heatmap_csv(), "1,2,1\n1,0,1\n");
+        assert_eq!(
+            analysis.steps_csv(),
+            "step,top_left,top_right,bottom_left,bottom_right,max_stacked
+0,1,0,0,1,1
+1,0,0,0,0,2
+2,0,1,1,0,1
+"
+        );
+        assert_eq!(analysis.render_heatmap(), ".@.\n. .\n");
+
+        let mut robots = process_input(EXAMPLE);
+        let analysis = analyse_robots(&mut robots, 101, 7, 11);
+        assert_eq!(analysis.period, 77);
+        assert_eq!(analysis.heatmap.iter().flatten().sum::<usize>(), 12 * 101);
+        assert_eq!(analysis.quadrant_counts[100].iter().product::<usize>(), 12);
+    }
+
+    #[test]
+    fn test_find_picture() {
+        let grid_height: usize = 103;
+        let grid_width: usize = 101;
+        let picture_step: isize = 6512;
+        let mut rng = XorShift(14);
+        let mut random = |n: isize| rng.below(n as usize) as isize;
+        // Robots that gather in a 15x15 square at the picture step
+        let mut robots: Vec<Robot> = Vec::new();
+        for i in 0..300 {
+            let (vx, vy) = (random(201) - 100, random(201) - 100);
+            let (target_x, target_y) = if i < 200 {
+                (40 + random(15), 50 + random(15))
+            } else {
+                (random(101), random(103))
+            };
+            let (x, y) = wrapped_next_location(
+                target_x,
+                target_y,
+                -vx * picture_step,
+                -vy * picture_step,
+                grid_width,
+                grid_height,
+            );
+            robots.push(Robot { x, y, vx, vy });
+        }
+        let result = find_picture(&robots, grid_height, grid_width);
+        assert_eq!(result, Some(picture_step as usize));
+
+        // Pure noise has no picture
+        let noise: Vec<Robot> = robots.split_off(200);
+        assert_eq!(find_picture(&noise, grid_height, grid_width), None);
+    }
 }
@@ -1,6 +1,10 @@
-use crate::util::{input_to_grid_map, read_data_from_file};
+use crate::util::{grid_map_to_string, input_to_grid_map, next_xy, read_data_from_file};
 use core::panic;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, Write};
+
+/// Moves per line when exporting a recorded session, as in the puzzle example.
+const INSTRUCTION_LINE_WIDTH: usize = 70;
 
 fn score(grid: &HashMap<(usize, usize), char>, score_char: char) -> usize {
     let mut score: usize = 0;
@@ -12,73 +16,76 @@ fn score(grid: &HashMap<(usize, usize), char>, score_char: char) -> usize {
     score
 }
 
-fn move_recursive(
-    x: usize,
-    y: usize,
-    instruction: (isize, isize),
-    grid: &mut HashMap<(usize, usize), char>,
-) {
-    let this_char = *grid.get(&(x, y)).unwrap();
-    let next_x = x.checked_add_signed(instruction.0).unwrap();
-    let next_y = y.checked_add_signed(instruction.1).unwrap();
-    let next_char = *grid.get(&(next_x, next_y)).unwrap();
-    match (next_char, next_x != x) {
-        ('[', true) => {
-            move_recursive(next_x, next_y, instruction, grid);
-            move_recursive(next_x, next_y + 1, instruction, grid);
-        }
-        (']', true) => {
-            move_recursive(next_x, next_y, instruction, grid);
-            move_recursive(next_x, next_y - 1, instruction, grid);
-        }
-        ('#', false) => {
-            panic!("Shouldn't be moving me");
-        }
-        ('#', true) => {
-            panic!("Shouldn't be moving me");
+/// Cells of the box covering `location`, if there is one. `O` is a single
+/// box, `[`, `=` and `]` make one crate spanning a row from `[` to `]`, and any
+/// other letter labels a box of any shape: all touching cells with that letter.
+fn find_object(
+    location: (usize, usize),
+    grid: &HashMap<(usize, usize), char>,
+) -> Option<Vec<(usize, usize)>> {
+    let ch = *grid.get(&location)?;
+    match ch {
+        'O' => Some(vec![location]),
+        '[' | '=' | ']' => {
+            let (x, mut y) = location;
+            while grid.get(&(x, y)) != Some(&'[') {
+                y = y.checked_sub(1)?;
+            }
+            let mut cells = vec![(x, y)];
+            while grid.get(&(x, y)) != Some(&']') {
+                y += 1;
+                if !matches!(grid.get(&(x, y)), Some('=' | ']')) {
+                    return None;
+                }
+                cells.push((x, y));
+            }
+            Some(cells)
         }
-        ('.', true) => {}
-        ('.', false) => {}
-        _ => {
-            move_recursive(next_x, next_y, instruction, grid);
+        _ if ch.is_ascii_alphabetic() => {
+            let mut cells = vec![location];
+            let mut i = 0;
+            while i < cells.len() {
+                let (x, y) = cells[i];
+                i += 1;
+                for direction in ['^', '>', 'v', '<'] {
+                    if let Some(next) = next_xy(x, y, direction) {
+                        if grid.get(&next) == Some(&ch) && !cells.contains(&next) {
+                            cells.push(next);
+                        }
+                    }
+                }
+            }
+            Some(cells)
         }
+        _ => None,
     }
-    grid.insert((next_x, next_y), this_char);
-    grid.insert((x, y), '.');
 }
 
-fn check_moveable(
-    x: usize,
-    y: usize,
-    instruction: (isize, isize),
+/// Every cell that moves when the robot pushes in `instruction`'s direction:
+/// the robot and all boxes it shoves, directly or through other boxes. `None`
+/// if anything in the way is a wall or off the map.
+fn plan_push(
+    instruction: char,
+    robot_location: (usize, usize),
     grid: &HashMap<(usize, usize), char>,
-) -> bool {
-    let this_char = *grid.get(&(x, y)).unwrap();
-    if this_char == '.' {
-        return true;
-    } else if this_char == '#' {
-        return false;
-    }
-    let next_x = x.checked_add_signed(instruction.0).unwrap();
-    let next_y = y.checked_add_signed(instruction.1).unwrap();
-
-    let moveable = match (this_char, next_x != x) {
-        ('[', true) => {
-            //direction
-            let extra_side_y = next_y + 1;
-            let this_moveable = check_moveable(next_x, next_y, instruction, grid);
-            let extra_moveable = check_moveable(next_x, extra_side_y, instruction, grid);
-            this_moveable & extra_moveable
-        }
-        (']', true) => {
-            let extra_side_y = next_y - 1;
-            let this_moveable = check_moveable(next_x, next_y, instruction, grid);
-            let extra_moveable = check_moveable(next_x, extra_side_y, instruction, grid);
-            this_moveable & extra_moveable
-        }
-        _ => check_moveable(next_x, next_y, instruction, grid),
-    };
-    moveable
+) -> Option<Vec<(usize, usize)>> {
+    let mut moving = vec![robot_location];
+    let mut i = 0;
+    while i < moving.len() {
+        let (x, y) = moving[i];
+        i += 1;
+        let next = next_xy(x, y, instruction)?;
+        match grid.get(&next) {
+            None | Some('#') => return None,
+            Some(_) if moving.contains(&next) => {}
+            Some(_) => {
+                if let Some(cells) = find_object(next, grid) {
+                    moving.extend(cells);
+                }
+            }
+        }
+    }
+    Some(moving)
 }
 
 fn process_instruction(
@@ -86,21 +93,18 @@ fn process_instruction(
     robot_location: (usize, usize),
     grid: &mut HashMap<(usize, usize), char>,
 ) -> (usize, usize) {
-    let instruction: (isize, isize) = match instruction {
-        '>' => (0, 1),
-        '^' => (-1, 0),
-        '<' => (0, -1),
-        'v' => (1, 0),
-        _ => panic!("Unknown instruction"),
+    let Some(moving) = plan_push(instruction, robot_location, grid) else {
+        return robot_location;
     };
-    if check_moveable(robot_location.0, robot_location.1, instruction, grid) {
-        move_recursive(robot_location.0, robot_location.1, instruction, grid);
-        return (
-            robot_location.0.checked_add_signed(instruction.0).unwrap(),
-            robot_location.1.checked_add_signed(instruction.1).unwrap(),
-        );
+    // Lift everything first so objects can move into each other's old cells
+    let lifted: Vec<((usize, usize), char)> = moving
+        .iter()
+        .map(|location| (*location, grid.insert(*location, '.').unwrap()))
+        .collect();
+    for ((x, y), ch) in lifted {
+        grid.insert(next_xy(x, y, instruction).unwrap(), ch);
     }
-    robot_location
+    next_xy(robot_location.0, robot_location.1, instruction).unwrap()
 }
 
 fn find_robot(grid: &HashMap<(usize, usize), char>) -> (usize, usize) {
@@ -132,21 +136,32 @@ pub fn part_1(input_uri: &str) -> usize {
     score(&grid, 'O')
 }
 
-fn double_width(grid: &HashMap<(usize, usize), char>) -> HashMap<(usize, usize), char> {
-    let mut double_width_grid = HashMap::new();
+/// Stretches every tile to `k` tiles. Boxes become crates `k` wide (`[`, `=`
+/// padding, `]`), and labelled boxes keep their label across the new width.
+fn scale_width(grid: &HashMap<(usize, usize), char>, k: usize) -> HashMap<(usize, usize), char> {
+    let mut scaled_grid = HashMap::new();
     for ((x, y), ch) in grid {
-        let j = y * 2;
-        let chars = match *ch {
-            '#' => ('#', '#'),
-            'O' => ('[', ']'),
-            '.' => ('.', '.'),
-            '@' => ('@', '.'),
-            _ => panic!("Unknown character"),
-        };
-        double_width_grid.insert((*x, j), chars.0);
-        double_width_grid.insert((*x, j + 1), chars.1);
+        let j = y * k;
+        for offset in 0..k {
+            let scaled = match (*ch, offset) {
+                ('O', _) if k == 1 => 'O',
+                ('O', 0) => '[',
+                ('O', _) if offset == k - 1 => ']',
+                ('O', _) => '=',
+                ('@', 0) => '@',
+                ('@', _) => '.',
+                ('#' | '.', _) => *ch,
+                (label, _) if label.is_ascii_alphabetic() => label,
+                _ => panic!("Unknown character"),
+            };
+            scaled_grid.insert((*x, j + offset), scaled);
+        }
     }
-    double_width_grid
+    scaled_grid
+}
+
+fn double_width(grid: &HashMap<(usize, usize), char>) -> HashMap<(usize, usize), char> {
+    scale_width(grid, 2)
 }
 
 pub fn part_2(input_uri: &str) -> usize {
@@ -160,6 +175,406 @@ pub fn part_2(input_uri: &str) -> usize {
     score(&double_grid, '[')
 }
 
+/// One move of the robot, with enough detail to undo it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveDelta {
+    pub instruction: char,
+    /// Changed cells as (location, before, after).
+    pub changes: Vec<((usize, usize), char, char)>,
+    pub blocked: bool,
+    pub robot_before: (usize, usize),
+    pub robot_after: (usize, usize),
+}
+
+/// Cells a push from `robot_location` changes: everything that moves and
+/// the cells it moves into.
+fn push_region(
+    instruction: char,
+    robot_location: (usize, usize),
+    grid: &HashMap<(usize, usize), char>,
+) -> Vec<(usize, usize)> {
+    let mut region = plan_push(instruction, robot_location, grid).unwrap_or_default();
+    for (x, y) in region.clone() {
+        let next = next_xy(x, y, instruction).unwrap();
+        if !region.contains(&next) {
+            region.push(next);
+        }
+    }
+    region
+}
+
+/// A warehouse that keeps every move as a reversible delta, so a run can be
+/// stepped backwards and forwards to a given instruction index.
+pub struct WarehouseSession {
+    grid: HashMap<(usize, usize), char>,
+    robot_location: (usize, usize),
+    history: Vec<MoveDelta>,
+    position: usize,
+}
+
+impl WarehouseSession {
+    pub fn new(grid: HashMap<(usize, usize), char>) -> Self {
+        let robot_location = find_robot(&grid);
+        WarehouseSession {
+            grid,
+            robot_location,
+            history: Vec::new(),
+            position: 0,
+        }
+    }
+
+    pub fn grid(&self) -> &HashMap<(usize, usize), char> {
+        &self.grid
+    }
+
+    pub fn robot_location(&self) -> (usize, usize) {
+        self.robot_location
+    }
+
+    /// Moves recorded so far, including any that were undone.
+    pub fn history(&self) -> &[MoveDelta] {
+        &self.history
+    }
+
+    /// Number of moves currently applied.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Applies a move, dropping any undone moves after the current position.
+    /// `None` and nothing recorded if `instruction` is not one of `<^>v`.
+    pub fn apply(&mut self, instruction: char) -> Option<&MoveDelta> {
+        if !matches!(instruction, '<' | '^' | '>' | 'v') {
+            return None;
+        }
+        let region = push_region(instruction, self.robot_location, &self.grid);
+        let before: Vec<char> = region.iter().map(|location| self.grid[location]).collect();
+        let robot_before = self.robot_location;
+        self.robot_location = process_instruction(instruction, robot_before, &mut self.grid);
+        let changes = region
+            .into_iter()
+            .zip(before)
+            .filter_map(|(location, before)| {
+                let after = self.grid[&location];
+                (before != after).then_some((location, before, after))
+            })
+            .collect();
+        self.history.truncate(self.position);
+        self.history.push(MoveDelta {
+            instruction,
+            changes,
+            blocked: robot_before == self.robot_location,
+            robot_before,
+            robot_after: self.robot_location,
+        });
+        self.position += 1;
+        self.history.last()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if self.position == 0 {
+            return false;
+        }
+        self.position -= 1;
+        let delta = &self.history[self.position];
+        for (location, before, _) in &delta.changes {
+            self.grid.insert(*location, *before);
+        }
+        self.robot_location = delta.robot_before;
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(delta) = self.history.get(self.position) else {
+            return false;
+        };
+        for (location, _, after) in &delta.changes {
+            self.grid.insert(*location, *after);
+        }
+        self.robot_location = delta.robot_after;
+        self.position += 1;
+        true
+    }
+
+    /// Undoes or redoes until exactly `k` moves are applied, or as close as
+    /// the recorded history allows.
+    pub fn jump_to(&mut self, k: usize) -> usize {
+        while self.position > k && self.undo() {}
+        while self.position < k && self.redo() {}
+        self.position
+    }
+
+    /// Applies every instruction in a move list, skipping line breaks and
+    /// anything else that is not a move.
+    pub fn replay(&mut self, instructions: &str) {
+        for instruction in instructions.chars() {
+            self.apply(instruction);
+        }
+    }
+
+    /// Replays the moves in a file, which may be a move list on its own or a
+    /// full puzzle input with the map first.
+    pub fn replay_file(&mut self, input_uri: &str) {
+        let input = read_data_from_file(input_uri);
+        let instructions = match input.split_once("\n\n") {
+            Some((_, instructions)) => instructions,
+            None => &input,
+        };
+        self.replay(instructions);
+    }
+
+    /// GPS score of the boxes, for either the normal or the double width map.
+    pub fn score(&self) -> usize {
+        if self.grid.values().any(|ch| *ch == '[') {
+            score(&self.grid, '[')
+        } else {
+            score(&self.grid, 'O')
+        }
+    }
+
+    /// Applied moves as a move list in the puzzle's own format.
+    pub fn export_instructions(&self) -> String {
+        let instructions: Vec<char> = self.history[..self.position]
+            .iter()
+            .map(|delta| delta.instruction)
+            .collect();
+        instructions
+            .chunks(INSTRUCTION_LINE_WIDTH)
+            .map(|line| line.iter().collect::<String>() + "\n")
+            .collect()
+    }
+}
+
+/// Commands in one line of play input. Arrow keys arrive as escape sequences
+/// since the terminal stays in line mode.
+fn parse_commands(line: &str) -> Vec<char> {
+    let line = line
+        .replace("\x1b[A", "^")
+        .replace("\x1b[B", "v")
+        .replace("\x1b[C", ">")
+        .replace("\x1b[D", "<");
+    line.chars()
+        .filter_map(|ch| match ch.to_ascii_lowercase() {
+            'w' | '^' => Some('^'),
+            'a' | '<' => Some('<'),
+            's' | 'v' => Some('v'),
+            'd' | '>' => Some('>'),
+            'u' | 'r' | 'q' => Some(ch.to_ascii_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Plays the warehouse from `input`, one line of commands at a time: WASD or
+/// arrows to move, `u` to undo, `r` to redo and `q` to quit. The map and GPS
+/// score are redrawn after every command.
+pub fn play<R: BufRead, W: Write>(
+    session: &mut WarehouseSession,
+    input: R,
+    output: &mut W,
+) -> io::Result<()> {
+    write!(output, "{}", grid_map_to_string(session.grid()))?;
+    writeln!(output, "GPS: {}", session.score())?;
+    for line in input.lines() {
+        for command in parse_commands(&line?) {
+            match command {
+                'u' => {
+                    session.undo();
+                }
+                'r' => {
+                    session.redo();
+                }
+                'q' => return Ok(()),
+                instruction => {
+                    session.apply(instruction);
+                }
+            }
+            write!(output, "{}", grid_map_to_string(session.grid()))?;
+            writeln!(output, "GPS: {}", session.score())?;
+        }
+    }
+    Ok(())
+}
+
+/// Interactive play on the terminal. The file only needs the map; any moves
+/// after it are ignored. Prints the recorded moves when the game ends.
+pub fn play_file(input_uri: &str, double: bool) -> io::Result<()> {
+    let input = read_data_from_file(input_uri);
+    let map = input.split("\n\n").next().unwrap_or_default();
+    let mut grid = input_to_grid_map(map);
+    if double {
+        grid = double_width(&grid);
+    }
+    let mut session = WarehouseSession::new(grid);
+    play(&mut session, io::stdin().lock(), &mut io::stdout())?;
+    print!("{}", session.export_instructions());
+    Ok(())
+}
+
+/// Cells a box has to end up on.
+type Targets = HashSet<(usize, usize)>;
+
+/// Splits a planning map into the warehouse and its target cells. Targets are
+/// `+` when empty and `*` when a box already sits on one.
+fn parse_targets(map: &str) -> (HashMap<(usize, usize), char>, Targets) {
+    let mut grid = input_to_grid_map(map);
+    let mut targets: Targets = HashSet::new();
+    for (location, ch) in grid.iter_mut() {
+        match *ch {
+            '+' => *ch = '.',
+            '*' => *ch = 'O',
+            _ => continue,
+        }
+        targets.insert(*location);
+    }
+    (grid, targets)
+}
+
+/// Every box on the map, one list of cells per box.
+fn find_objects(grid: &HashMap<(usize, usize), char>) -> Vec<Vec<(usize, usize)>> {
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    let mut objects = Vec::new();
+    let mut locations: Vec<&(usize, usize)> = grid.keys().collect();
+    locations.sort();
+    for location in locations {
+        if seen.contains(location) {
+            continue;
+        }
+        if let Some(cells) = find_object(*location, grid) {
+            seen.extend(cells.iter().copied());
+            objects.push(cells);
+        }
+    }
+    objects
+}
+
+/// Whether the robot could push `object` towards `direction` in `grid`,
+/// shoving any row of boxes in front of or behind it along too. The robot may
+/// stand on any empty cell, so only walls and the boxes left in `grid` count.
+fn can_move(
+    object: &[(usize, usize)],
+    direction: char,
+    grid: &HashMap<(usize, usize), char>,
+) -> bool {
+    let opposite = match direction {
+        '^' => 'v',
+        'v' => '^',
+        '<' => '>',
+        _ => '<',
+    };
+    object.iter().any(|(x, y)| {
+        // Back up through any boxes behind to somewhere the robot can stand
+        let mut behind = next_xy(*x, *y, opposite);
+        while let Some(cell) = behind {
+            if object.contains(&cell) || find_object(cell, grid).is_none() {
+                break;
+            }
+            behind = next_xy(cell.0, cell.1, opposite);
+        }
+        match behind {
+            Some(cell) if !object.contains(&cell) && grid.get(&cell) == Some(&'.') => {
+                plan_push(direction, cell, grid).is_some_and(|moving| moving.contains(&(*x, *y)))
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Whether some box can never move again while covering a cell that is not a
+/// target. Starts from every box being stuck and frees any box the robot could
+/// push with only the stuck boxes still in place, chain pushes included;
+/// whatever is left blocks itself and the walls forever.
+fn is_deadlocked(grid: &HashMap<(usize, usize), char>, targets: &Targets) -> bool {
+    let mut frozen = find_objects(grid);
+    loop {
+        let stuck: HashSet<(usize, usize)> = frozen.iter().flatten().copied().collect();
+        let view: HashMap<(usize, usize), char> = grid
+            .iter()
+            .map(|(location, ch)| match ch {
+                '#' => (*location, '#'),
+                _ if stuck.contains(location) => (*location, *ch),
+                _ => (*location, '.'),
+            })
+            .collect();
+        let before = frozen.len();
+        frozen.retain(|object| {
+            !['^', '>', 'v', '<']
+                .iter()
+                .any(|direction| can_move(object, *direction, &view))
+        });
+        if frozen.len() == before {
+            break;
+        }
+    }
+    frozen
+        .iter()
+        .flatten()
+        .any(|location| !targets.contains(location))
+}
+
+/// Shortest move list in the puzzle's `<^>v` format that covers every target
+/// with a box, found by breadth-first search over warehouse states using the
+/// normal movement rules. When there are exactly as many box cells as targets,
+/// states with a stuck box off target are pruned. Gives up after `max_states`.
+fn plan_moves(
+    grid: &HashMap<(usize, usize), char>,
+    targets: &Targets,
+    max_states: usize,
+) -> Option<String> {
+    let box_cells = find_objects(grid).iter().flatten().count();
+    let prune = box_cells == targets.len();
+    let solved = |grid: &HashMap<(usize, usize), char>| {
+        targets
+            .iter()
+            .all(|target| find_object(*target, grid).is_some())
+    };
+    let key = |grid: &HashMap<(usize, usize), char>| {
+        let mut key: Vec<((usize, usize), char)> = grid
+            .iter()
+            .filter(|(_, ch)| !matches!(ch, '#' | '.'))
+            .map(|(location, ch)| (*location, *ch))
+            .collect();
+        key.sort();
+        key
+    };
+
+    let mut seen = HashSet::from([key(grid)]);
+    let mut queue = VecDeque::from([(grid.clone(), find_robot(grid), String::new())]);
+    while let Some((grid, robot_location, moves)) = queue.pop_front() {
+        if solved(&grid) {
+            return Some(moves);
+        }
+        for instruction in ['^', '>', 'v', '<'] {
+            let mut next_grid = grid.clone();
+            let next_robot = process_instruction(instruction, robot_location, &mut next_grid);
+            if next_robot == robot_location || !seen.insert(key(&next_grid)) {
+                continue;
+            }
+            if prune && is_deadlocked(&next_grid, targets) {
+                continue;
+            }
+            if seen.len() > max_states {
+                return None;
+            }
+            queue.push_back((next_grid, next_robot, format!("{moves}{instruction}")));
+        }
+    }
+    None
+}
+
+/// Plans moves for a map with `+`/`*` targets, stretched to `width` tiles
+/// per cell like `scale_width`.
+pub fn plan_file(input_uri: &str, width: usize, max_states: usize) -> Option<String> {
+    let input = read_data_from_file(input_uri);
+    let map = input.split("\n\n").next().unwrap_or_default();
+    let (grid, targets) = parse_targets(map);
+    let targets: Targets = targets
+        .iter()
+        .flat_map(|(x, y)| (0..width).map(move |offset| (*x, y * width + offset)))
+        .collect();
+    plan_moves(&scale_width(&grid, width), &targets, max_states)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -206,4 +621,272 @@ mod tests {
         let result = part_2("data/day15/test10.txt");
         assert_eq!(result, 1021);
     }
+
+    const SMALL_EXAMPLE: &str = "########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+<^^>>>vv<v>>v<<";
+
+    #[test]
+    fn test_session() {
+        let (grid, instructions) = process_input(SMALL_EXAMPLE);
+        let instructions: String = instructions.into_iter().collect();
+        let mut session = WarehouseSession::new(grid.clone());
+
+        let delta = session.apply('<').unwrap();
+        assert!(delta.blocked);
+        assert!(delta.changes.is_empty());
+        let delta = session.apply('^').unwrap();
+        assert!(!delta.blocked);
+        assert_eq!(delta.changes, vec![((2, 2), '@', '.'), ((1, 2), '.', '@')]);
+
+        session.jump_to(0);
+        session.replay(&instructions);
+        assert_eq!(session.position(), 15);
+        assert_eq!(score(session.grid(), 'O'), 2028);
+
+        session.jump_to(0);
+        assert_eq!(*session.grid(), grid);
+        assert_eq!(session.robot_location(), (2, 2));
+
+        let mut fresh = WarehouseSession::new(grid.clone());
+        fresh.replay(&instructions[..7]);
+        assert_eq!(session.jump_to(7), 7);
+        assert_eq!(session.grid(), fresh.grid());
+        assert_eq!(session.robot_location(), fresh.robot_location());
+
+        assert_eq!(session.jump_to(100), 15);
+        assert!(!session.redo());
+        session.jump_to(3);
+        session.apply('v');
+        assert_eq!(session.history().len(), 4);
+        assert!(!session.redo());
+
+        // Only `<^>v` are moves; map glyphs and other text are not recorded
+        let mut session = WarehouseSession::new(grid.clone());
+        assert_eq!(session.apply('#'), None);
+        assert_eq!(session.apply('x'), None);
+        assert!(session.history().is_empty());
+        session.replay(SMALL_EXAMPLE);
+        assert_eq!(session.position(), 15);
+        assert_eq!(session.export_instructions(), instructions.clone() + "\n");
+    }
+
+    #[test]
+    fn test_session_wide_boxes() {
+        let (grid, instructions) = process_input(SMALL_EXAMPLE);
+        let double_grid = double_width(&grid);
+        let mut expected = double_grid.clone();
+        let mut robot_location = find_robot(&expected);
+        let mut session = WarehouseSession::new(double_grid.clone());
+        for instruction in instructions {
+            robot_location = process_instruction(instruction, robot_location, &mut expected);
+            session.apply(instruction);
+            assert_eq!(*session.grid(), expected);
+        }
+        assert_eq!(session.robot_location(), robot_location);
+        session.jump_to(0);
+        assert_eq!(*session.grid(), double_grid);
+    }
+
+    #[test]
+    fn test_play() {
+        let (grid, instructions) = process_input(SMALL_EXAMPLE);
+        let mut session = WarehouseSession::new(grid.clone());
+        let input = "a\nww\n\x1b[C\x1b[C>\nssx\nuur\n";
+        let mut output: Vec<u8> = Vec::new();
+        play(&mut session, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        // The starting map, then one redraw per command
+        assert_eq!(output.matches("GPS: ").count(), 12);
+        assert!(output.starts_with(&grid_map_to_string(&grid)));
+
+        let mut expected = WarehouseSession::new(grid.clone());
+        expected.replay("<^^>>>v");
+        assert_eq!(session.grid(), expected.grid());
+        assert!(output.ends_with(&format!(
+            "{}GPS: {}\n",
+            grid_map_to_string(expected.grid()),
+            expected.score()
+        )));
+        assert_eq!(session.export_instructions(), "<^^>>>v\n");
+
+        // Quitting stops reading, and the export splits long move lists
+        let mut session = WarehouseSession::new(double_width(&grid));
+        let moves: String = instructions.iter().cycle().take(100).collect();
+        let input = format!("{moves}\nq\nwasd\n");
+        play(&mut session, input.as_bytes(), &mut Vec::new()).unwrap();
+        assert_eq!(session.position(), 100);
+        let export = session.export_instructions();
+        assert_eq!(
+            export.lines().map(str::len).collect::<Vec<_>>(),
+            vec![70, 30]
+        );
+        assert_eq!(export.replace('\n', ""), moves);
+    }
+
+    fn grid_from(rows: &str) -> HashMap<(usize, usize), char> {
+        input_to_grid_map(rows)
+    }
+
+    #[test]
+    fn test_scale_width() {
+        let grid = grid_from("#####\n#@O.#\n#####");
+        assert_eq!(scale_width(&grid, 2), double_width(&grid));
+        let tripled = scale_width(&grid, 3);
+        assert_eq!(
+            grid_map_to_string(&tripled),
+            "###############\n###@..[=]...###\n###############\n"
+        );
+        assert_eq!(
+            find_object((1, 7), &tripled),
+            Some(vec![(1, 6), (1, 7), (1, 8)])
+        );
+        assert_eq!(scale_width(&grid, 1), grid);
+    }
+
+    #[test]
+    fn test_wide_crates() {
+        // A triple-width crate resting on two others shifts all three at once
+        let mut grid = grid_from(
+            "##########
+#........#
+#[=][=]..#
+#.[=]....#
+#..@.....#
+##########",
+        );
+        let robot = process_instruction('^', (4, 3), &mut grid);
+        assert_eq!(robot, (3, 3));
+        assert_eq!(
+            grid_map_to_string(&grid),
+            "##########
+#[=][=]..#
+#.[=]....#
+#..@.....#
+#........#
+##########
+"
+        );
+        // Now the top row is against the wall, so nothing moves
+        let robot = process_instruction('^', robot, &mut grid);
+        assert_eq!(robot, (3, 3));
+        assert_eq!(grid[&(1, 1)], '[');
+    }
+
+    #[test]
+    fn test_shaped_boxes() {
+        // L-shaped A and a two-tall B pushed right together
+        let mut grid = grid_from(
+            "#########
+#.A.....#
+#@AAB...#
+#...B...#
+#########",
+        );
+        let robot = process_instruction('>', (2, 1), &mut grid);
+        assert_eq!(robot, (2, 2));
+        assert_eq!(
+            grid_map_to_string(&grid),
+            "#########
+#..A....#
+#.@AAB..#
+#....B..#
+#########
+"
+        );
+        let mut robot = robot;
+        for _ in 0..3 {
+            robot = process_instruction('>', robot, &mut grid);
+        }
+        // B reaches the wall on the third push and holds everything back
+        assert_eq!(robot, (2, 4));
+        assert_eq!(
+            grid_map_to_string(&grid),
+            "#########
+#....A..#
+#...@AAB#
+#......B#
+#########
+"
+        );
+        // Pushing a box into a wall is refused instead of panicking
+        let mut grid = grid_from("#####\n#@AA#\n#####");
+        assert_eq!(process_instruction('>', (1, 1), &mut grid), (1, 1));
+        assert_eq!(plan_push('>', (1, 1), &grid), None);
+    }
+
+    #[test]
+    fn test_plan_moves() {
+        let (grid, targets) = parse_targets("#######\n#@.O.+#\n#######");
+        assert_eq!(plan_moves(&grid, &targets, 10_000), Some(">>>".to_string()));
+
+        let (grid, targets) = parse_targets(
+            "#######
+#.....#
+#.O.+.#
+#@....#
+#######",
+        );
+        assert_eq!(plan_moves(&grid, &targets, 10_000), Some("^>>".to_string()));
+
+        let (grid, targets) = parse_targets(
+            "#######
+#.....#
+#.O.+.#
+#.*...#
+#@.O+.#
+#.....#
+#######",
+        );
+        let moves = plan_moves(&grid, &targets, 100_000).unwrap();
+        let mut session = WarehouseSession::new(grid.clone());
+        session.replay(&moves);
+        assert!(targets.iter().all(|target| session.grid()[target] == 'O'));
+
+        // Wide crates follow the part 2 rules
+        let (grid, targets) = parse_targets("#########\n#@[]..++#\n#########");
+        assert_eq!(
+            plan_moves(&grid, &targets, 10_000),
+            Some(">>>>".to_string())
+        );
+
+        // A box in a corner can never reach the target
+        let (grid, targets) = parse_targets("#######\n#O.@.+#\n#######");
+        assert!(is_deadlocked(&grid, &targets));
+        assert_eq!(plan_moves(&grid, &targets, 10_000), None);
+    }
+
+    #[test]
+    fn test_is_deadlocked() {
+        let no_targets = HashSet::new();
+        // A row of boxes can be pushed as one, so neither a pair along a wall
+        // nor a 2x2 block in the open is stuck
+        let grid = grid_from("######\n#.OO.#\n#....#\n#.@..#\n######");
+        assert!(!is_deadlocked(&grid, &no_targets));
+        let grid = grid_from("#######\n#.....#\n#.OO..#\n#.OO..#\n#...@.#\n#######");
+        assert!(!is_deadlocked(&grid, &no_targets));
+        let grid = grid_from("#######\n#.....#\n#.OO..#\n#...@.#\n#######");
+        assert!(!is_deadlocked(&grid, &no_targets));
+        // A pair in a corridor against the end wall has nowhere to push from
+        let grid = grid_from("#####\n#OO@#\n#####");
+        assert!(is_deadlocked(&grid, &no_targets));
+        // A stuck box is fine when it sits on a target
+        let (grid, targets) = parse_targets("#####\n#*.@#\n#####");
+        assert!(!is_deadlocked(&grid, &targets));
+    }
+
+    #[test]
+    fn test_plan_chain_push() {
+        // Both boxes go in one push, which a box-at-a-time freeze check misses
+        let (grid, targets) = parse_targets("#######\n#@OO++#\n#######");
+        assert!(!is_deadlocked(&grid, &targets));
+        assert_eq!(plan_moves(&grid, &targets, 10_000), Some(">>".to_string()));
+    }
 }
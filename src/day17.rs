@@ -1,9 +1,84 @@
-use std::usize;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    hash::Hash,
+    str::FromStr,
+};
 
 use crate::util::read_data_from_file;
+use num_bigint::BigUint;
 use regex::Regex;
 
-fn result_join(result: &Vec<usize>) -> String {
+/// Register word of the 3-bit computer. The `dv` instructions divide by a
+/// power of two, so they are implemented as shifts that saturate to 0 once
+/// the exponent reaches the width of the word.
+pub trait Word: Clone + Eq + Hash + Debug + Display + FromStr<Err: Debug> {
+    fn from_operand(operand: usize) -> Self;
+    fn div_pow2(&self, exponent: &Self) -> Self;
+    fn xor(&self, other: &Self) -> Self;
+    fn low_bits(&self) -> usize;
+    fn is_zero(&self) -> bool;
+}
+
+macro_rules! impl_word {
+    ($t:ty) => {
+        impl Word for $t {
+            fn from_operand(operand: usize) -> Self {
+                operand as $t
+            }
+
+            fn div_pow2(&self, exponent: &Self) -> Self {
+                match u32::try_from(*exponent) {
+                    Ok(shift) => self.checked_shr(shift).unwrap_or(0),
+                    Err(_) => 0,
+                }
+            }
+
+            fn xor(&self, other: &Self) -> Self {
+                self ^ other
+            }
+
+            fn low_bits(&self) -> usize {
+                (self % 8) as usize
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+        }
+    };
+}
+
+impl_word!(u64);
+impl_word!(u128);
+
+impl Word for BigUint {
+    fn from_operand(operand: usize) -> Self {
+        BigUint::from(operand)
+    }
+
+    fn div_pow2(&self, exponent: &Self) -> Self {
+        // Anything wider than the value itself shifts everything out
+        match u64::try_from(exponent) {
+            Ok(shift) if shift < self.bits() => self >> shift,
+            _ => BigUint::ZERO,
+        }
+    }
+
+    fn xor(&self, other: &Self) -> Self {
+        self ^ other
+    }
+
+    fn low_bits(&self) -> usize {
+        self.iter_u32_digits().next().unwrap_or(0) as usize % 8
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == BigUint::ZERO
+    }
+}
+
+fn result_join(result: &[usize]) -> String {
     result
         .iter()
         .map(|x| x.to_string())
@@ -11,58 +86,53 @@ fn result_join(result: &Vec<usize>) -> String {
         .join(",")
 }
 
-fn cdv(
+fn cdv<W: Word>(
     operand: usize,
-    register_a: &usize,
-    register_b: &usize,
-    register_c: &mut usize,
+    register_a: &W,
+    register_b: &W,
+    register_c: &mut W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
-    let denominator: usize = 2;
-    *register_c =
-        *register_a / denominator.pow(combo(operand, register_a, register_b, register_c) as u32);
+    *register_c = register_a.div_pow2(&combo(operand, register_a, register_b, register_c));
     *instruction_pointer += 2;
     None
 }
 
-fn bdv(
+fn bdv<W: Word>(
     operand: usize,
-    register_a: &usize,
-    register_b: &mut usize,
-    register_c: &usize,
+    register_a: &W,
+    register_b: &mut W,
+    register_c: &W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
-    let denominator: usize = 2;
-    *register_b =
-        *register_a / denominator.pow(combo(operand, register_a, register_b, register_c) as u32);
+    *register_b = register_a.div_pow2(&combo(operand, register_a, register_b, register_c));
     *instruction_pointer += 2;
     None
 }
 
-fn out(
+fn out<W: Word>(
     operand: usize,
-    register_a: &usize,
-    register_b: &usize,
-    register_c: &usize,
+    register_a: &W,
+    register_b: &W,
+    register_c: &W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
     *instruction_pointer += 2;
-    Some(combo(operand, register_a, register_b, register_c) % 8)
+    Some(combo(operand, register_a, register_b, register_c).low_bits())
 }
 
-fn bxc(
-    register_b: &mut usize,
-    register_c: &usize,
+fn bxc<W: Word>(
+    register_b: &mut W,
+    register_c: &W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
-    let xor = *register_b ^ *register_c;
-    *register_b = xor;
+    *register_b = register_b.xor(register_c);
     *instruction_pointer += 2;
     None
 }
 
-fn jnz(operand: usize, register_a: &usize, instruction_pointer: &mut usize) -> Option<usize> {
-    if *register_a != 0 {
+fn jnz<W: Word>(operand: usize, register_a: &W, instruction_pointer: &mut usize) -> Option<usize> {
+    if !register_a.is_zero() {
         *instruction_pointer = operand;
     } else {
         *instruction_pointer += 2;
@@ -70,57 +140,56 @@ fn jnz(operand: usize, register_a: &usize, instruction_pointer: &mut usize) -> O
     None
 }
 
-fn bst(
+fn bst<W: Word>(
     operand: usize,
-    register_a: &usize,
-    register_b: &mut usize,
-    register_c: &usize,
+    register_a: &W,
+    register_b: &mut W,
+    register_c: &W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
-    *register_b = combo(operand, register_a, register_b, register_c) % 8;
+    *register_b = W::from_operand(combo(operand, register_a, register_b, register_c).low_bits());
     *instruction_pointer += 2;
     None
 }
 
-fn bxl(operand: usize, register_b: &mut usize, instruction_pointer: &mut usize) -> Option<usize> {
-    let xor = *register_b ^ operand;
-    *register_b = xor;
+fn bxl<W: Word>(
+    operand: usize,
+    register_b: &mut W,
+    instruction_pointer: &mut usize,
+) -> Option<usize> {
+    *register_b = register_b.xor(&W::from_operand(operand));
     *instruction_pointer += 2;
     None
 }
 
-fn adv(
+fn adv<W: Word>(
     operand: usize,
-    register_a: &mut usize,
-    register_b: &usize,
-    register_c: &usize,
+    register_a: &mut W,
+    register_b: &W,
+    register_c: &W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
-    let denominator: usize = 2;
-    *register_a /= denominator.pow(combo(operand, register_a, register_b, register_c) as u32);
+    *register_a = register_a.div_pow2(&combo(operand, register_a, register_b, register_c));
     *instruction_pointer += 2;
     None
 }
 
-fn combo(operand: usize, register_a: &usize, register_b: &usize, register_c: &usize) -> usize {
+fn combo<W: Word>(operand: usize, register_a: &W, register_b: &W, register_c: &W) -> W {
     match operand {
-        0 => 0,
-        1 => 1,
-        2 => 2,
-        3 => 3,
-        4 => *register_a,
-        5 => *register_b,
-        6 => *register_c,
+        0..=3 => W::from_operand(operand),
+        4 => register_a.clone(),
+        5 => register_b.clone(),
+        6 => register_c.clone(),
         _ => panic!("Invalid combo operand"),
     }
 }
 
-fn process_opcode(
+fn process_opcode<W: Word>(
     opcode: usize,
     operand: usize,
-    register_a: &mut usize,
-    register_b: &mut usize,
-    register_c: &mut usize,
+    register_a: &mut W,
+    register_b: &mut W,
+    register_c: &mut W,
     instruction_pointer: &mut usize,
 ) -> Option<usize> {
     match opcode {
@@ -169,11 +238,11 @@ fn process_opcode(
     }
 }
 
-fn process_instructions(
-    program: &Vec<usize>,
-    register_a: &mut usize,
-    register_b: &mut usize,
-    register_c: &mut usize,
+fn process_instructions<W: Word>(
+    program: &[usize],
+    register_a: &mut W,
+    register_b: &mut W,
+    register_c: &mut W,
     instruction_pointer: &mut usize,
 ) -> Vec<usize> {
     let mut result: Vec<usize> = Vec::new();
@@ -199,7 +268,213 @@ fn process_instructions(
     result
 }
 
-fn process_input(input: &str) -> (Vec<usize>, usize, usize, usize) {
+/// Bounds on a single run of the interpreter. A state (IP, A, B, C) that
+/// repeats means the program never halts, since execution is deterministic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutionLimits {
+    pub max_steps: Option<usize>,
+    pub detect_loops: bool,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        ExecutionLimits {
+            max_steps: Some(1_000_000),
+            detect_loops: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionError {
+    StepLimitExceeded {
+        steps: usize,
+    },
+    InfiniteLoop {
+        start_step: usize,
+        length: usize,
+        instruction_pointer: usize,
+    },
+}
+
+impl Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::StepLimitExceeded { steps } => {
+                write!(f, "program did not halt within {steps} steps")
+            }
+            ExecutionError::InfiniteLoop {
+                start_step,
+                length,
+                instruction_pointer,
+            } => write!(
+                f,
+                "program loops from step {start_step} (ip {instruction_pointer}) every {length} steps"
+            ),
+        }
+    }
+}
+
+/// Like `process_instructions`, but stops with an error instead of running
+/// forever. Output produced before the error is discarded.
+pub fn process_instructions_checked<W: Word>(
+    program: &[usize],
+    register_a: &mut W,
+    register_b: &mut W,
+    register_c: &mut W,
+    instruction_pointer: &mut usize,
+    limits: &ExecutionLimits,
+) -> Result<Vec<usize>, ExecutionError> {
+    let mut result: Vec<usize> = Vec::new();
+    let mut seen: HashMap<(usize, W, W, W), usize> = HashMap::new();
+    let mut steps = 0;
+    while let Some(opcode) = program.get(*instruction_pointer) {
+        if limits.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+            return Err(ExecutionError::StepLimitExceeded { steps });
+        }
+        if limits.detect_loops {
+            let state = (
+                *instruction_pointer,
+                register_a.clone(),
+                register_b.clone(),
+                register_c.clone(),
+            );
+            if let Some(start_step) = seen.insert(state, steps) {
+                return Err(ExecutionError::InfiniteLoop {
+                    start_step,
+                    length: steps - start_step,
+                    instruction_pointer: *instruction_pointer,
+                });
+            }
+        }
+        let operand = program.get(*instruction_pointer + 1).unwrap();
+        if let Some(out) = process_opcode(
+            *opcode,
+            *operand,
+            register_a,
+            register_b,
+            register_c,
+            instruction_pointer,
+        ) {
+            result.push(out);
+        }
+        steps += 1;
+    }
+    Ok(result)
+}
+
+type Registers<W> = [W; 3];
+
+/// A combo operand resolved at compile time.
+#[derive(Debug, Clone)]
+enum Source<W> {
+    Literal(W),
+    Register(usize),
+}
+
+/// One instruction with its operand already decoded.
+#[derive(Debug, Clone)]
+enum Decoded<W> {
+    Dv(usize, Source<W>),
+    Xor(W),
+    Set(Source<W>),
+    Jnz(usize),
+    Bxc,
+    Out(Source<W>),
+    Invalid(&'static str),
+}
+
+/// A program decoded once into one instruction per instruction pointer, with
+/// combo operands already resolved, so repeated runs skip opcode decoding.
+pub struct CompiledProgram<W: Word> {
+    ops: Vec<Decoded<W>>,
+}
+
+fn compile_combo<W: Word>(operand: usize) -> Option<Source<W>> {
+    match operand {
+        0..=3 => Some(Source::Literal(W::from_operand(operand))),
+        4..=6 => Some(Source::Register(operand - 4)),
+        _ => None,
+    }
+}
+
+fn compile_instruction<W: Word>(opcode: usize, operand: usize) -> Decoded<W> {
+    let combo = || match compile_combo(operand) {
+        Some(source) => Ok(source),
+        None => Err(Decoded::Invalid("Invalid combo operand")),
+    };
+    let decoded = match opcode {
+        0 => combo().map(|source| Decoded::Dv(0, source)),
+        1 => Ok(Decoded::Xor(W::from_operand(operand))),
+        2 => combo().map(Decoded::Set),
+        3 => Ok(Decoded::Jnz(operand)),
+        4 => Ok(Decoded::Bxc),
+        5 => combo().map(Decoded::Out),
+        6 => combo().map(|source| Decoded::Dv(1, source)),
+        7 => combo().map(|source| Decoded::Dv(2, source)),
+        _ => Err(Decoded::Invalid("Invalid opcode")),
+    };
+    decoded.unwrap_or_else(|invalid| invalid)
+}
+
+impl<W: Word> CompiledProgram<W> {
+    pub fn compile(program: &[usize]) -> Self {
+        // Jumps may land on odd addresses, so every position is decoded
+        let ops = (0..program.len())
+            .map(|ip| match program.get(ip + 1) {
+                Some(operand) => compile_instruction(program[ip], *operand),
+                None => Decoded::Invalid("Missing operand"),
+            })
+            .collect();
+        CompiledProgram { ops }
+    }
+
+    /// Runs from instruction pointer 0 until the program halts. Only the step
+    /// budget is checked here; loop detection would cost a hash per step.
+    pub fn run(
+        &self,
+        registers: &mut Registers<W>,
+        max_steps: Option<usize>,
+    ) -> Result<Vec<usize>, ExecutionError> {
+        let mut result: Vec<usize> = Vec::new();
+        let mut instruction_pointer = 0;
+        let mut steps = 0;
+        while let Some(op) = self.ops.get(instruction_pointer) {
+            if max_steps.is_some_and(|max_steps| steps >= max_steps) {
+                return Err(ExecutionError::StepLimitExceeded { steps });
+            }
+            instruction_pointer += 2;
+            match op {
+                Decoded::Dv(destination, Source::Literal(exponent)) => {
+                    registers[*destination] = registers[0].div_pow2(exponent)
+                }
+                Decoded::Dv(destination, Source::Register(register)) => {
+                    registers[*destination] = registers[0].div_pow2(&registers[*register])
+                }
+                Decoded::Xor(literal) => registers[1] = registers[1].xor(literal),
+                Decoded::Set(Source::Literal(value)) => registers[1] = value.clone(),
+                Decoded::Set(Source::Register(register)) => {
+                    registers[1] = W::from_operand(registers[*register].low_bits())
+                }
+                Decoded::Jnz(target) => {
+                    if !registers[0].is_zero() {
+                        instruction_pointer = *target;
+                    }
+                }
+                Decoded::Bxc => registers[1] = registers[1].xor(&registers[2]),
+                Decoded::Out(Source::Literal(value)) => result.push(value.low_bits()),
+                Decoded::Out(Source::Register(register)) => {
+                    result.push(registers[*register].low_bits())
+                }
+                Decoded::Invalid(message) => panic!("{message}"),
+            }
+            steps += 1;
+        }
+        Ok(result)
+    }
+}
+
+fn process_input<W: Word>(input: &str) -> (Vec<usize>, W, W, W) {
     let register_pattern =
         Regex::new(r"Register A: (\d+)\nRegister B: (\d+)\nRegister C: (\d+)").unwrap();
     let program_pattern = Regex::new(r"Program: ([\d+,]+)").unwrap();
@@ -216,9 +491,9 @@ fn process_input(input: &str) -> (Vec<usize>, usize, usize, usize) {
         .next()
         .unwrap()
         .extract();
-    let register_a = register_a.parse::<usize>().unwrap();
-    let register_b = register_b.parse::<usize>().unwrap();
-    let register_c = register_c.parse::<usize>().unwrap();
+    let register_a = register_a.parse::<W>().expect("Can't parse register");
+    let register_b = register_b.parse::<W>().expect("Can't parse register");
+    let register_c = register_c.parse::<W>().expect("Can't parse register");
     let program = program
         .split(',')
         .map(|x| x.parse::<usize>().unwrap())
@@ -227,8 +502,14 @@ fn process_input(input: &str) -> (Vec<usize>, usize, usize, usize) {
 }
 
 pub fn part_1(input_uri: &str) -> String {
+    part_1_with_word::<u64>(input_uri)
+}
+
+/// Runs part 1 with registers of the chosen width, e.g. `u128` or `BigUint`
+/// for programs whose register values do not fit in 64 bits.
+pub fn part_1_with_word<W: Word>(input_uri: &str) -> String {
     let input = read_data_from_file(input_uri);
-    let (program, mut register_a, mut register_b, mut register_c) = process_input(&input);
+    let (program, mut register_a, mut register_b, mut register_c) = process_input::<W>(&input);
     let result = process_instructions(
         &program,
         &mut register_a,
@@ -239,32 +520,39 @@ pub fn part_1(input_uri: &str) -> String {
     result_join(&result)
 }
 
-fn find(a: usize, i: usize, program: &Vec<usize>) {
-    let mut input_a = a;
-    let result = process_instructions(&program, &mut input_a, &mut 0, &mut 0, &mut 0);
+fn find(a: u64, i: usize, program: &Vec<usize>, compiled: &CompiledProgram<u64>) {
+    let max_steps = ExecutionLimits::default().max_steps;
+    let result = match compiled.run(&mut [a, 0, 0], max_steps) {
+        Ok(result) => result,
+        Err(_) => return,
+    };
     if result == *program {
         println!("{a}");
     } else if (program.ends_with(&result)) | (i == 0) {
-        for n in 0..8usize {
-            find(8 * a + n, i + 1, program);
+        for n in 0..8u64 {
+            find(8 * a + n, i + 1, program, compiled);
         }
     }
 }
 
 pub fn part_2(input_uri: &str) {
     let input = read_data_from_file(input_uri);
-    let (program, _, _, _) = process_input(&input);
+    let (program, _, _, _) = process_input::<u64>(&input);
+    let compiled = CompiledProgram::compile(&program);
 
-    find(0, 0, &program);
+    find(0, 0, &program, &compiled);
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use super::*;
+    use crate::util::XorShift;
 
     #[test]
     fn test_adv() {
-        let mut register_a = 4;
+        let mut register_a: u64 = 4;
         let mut register_b = 0;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -277,7 +565,7 @@ mod tests {
         );
         assert_eq!(register_a, 1);
         assert_eq!(instruction_pointer, 2);
-        let mut register_a = 5;
+        let mut register_a: u64 = 5;
         let mut register_b = 1;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -294,7 +582,7 @@ mod tests {
 
     #[test]
     fn test_process_instructions() {
-        let mut register_a = 10;
+        let mut register_a: u64 = 10;
         let mut register_b = 0;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -308,7 +596,7 @@ mod tests {
         );
         assert_eq!(result, vec![0, 1, 2]);
 
-        let mut register_a = 2024;
+        let mut register_a: u64 = 2024;
         let mut register_b = 0;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -323,7 +611,7 @@ mod tests {
         assert_eq!(result, vec![4, 2, 5, 6, 7, 7, 7, 7, 3, 1, 0]);
         assert_eq!(register_a, 0);
 
-        let mut register_a = 729;
+        let mut register_a: u64 = 729;
         let mut register_b = 0;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -337,7 +625,7 @@ mod tests {
         );
         assert_eq!(result, vec![4, 6, 3, 5, 6, 3, 5, 2, 1, 0]);
 
-        let mut register_a = 117440;
+        let mut register_a: u64 = 117440;
         let mut register_b = 0;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -354,7 +642,7 @@ mod tests {
 
     #[test]
     fn test_process_opcode() {
-        let mut register_a = 0;
+        let mut register_a: u64 = 0;
         let mut register_b = 0;
         let mut register_c = 9;
         let mut instruction_pointer = 0;
@@ -369,7 +657,7 @@ mod tests {
         assert_eq!(register_b, 1);
         assert_eq!(instruction_pointer, 2);
 
-        let mut register_a = 0;
+        let mut register_a: u64 = 0;
         let mut register_b = 29;
         let mut register_c = 0;
         let mut instruction_pointer = 0;
@@ -384,7 +672,7 @@ mod tests {
         assert_eq!(register_b, 26);
         assert_eq!(instruction_pointer, 2);
 
-        let mut register_a = 0;
+        let mut register_a: u64 = 0;
         let mut register_b = 2024;
         let mut register_c = 43690;
         let mut instruction_pointer = 0;
@@ -399,6 +687,393 @@ mod tests {
         assert_eq!(register_b, 44354);
         assert_eq!(instruction_pointer, 2);
     }
+    #[test]
+    fn test_large_shift() {
+        let mut register_a: u64 = u64::MAX;
+        let register_b: u64 = 64;
+        let register_c: u64 = 0;
+        let mut instruction_pointer = 0;
+        let _ = adv(
+            5,
+            &mut register_a,
+            &register_b,
+            &register_c,
+            &mut instruction_pointer,
+        );
+        assert_eq!(register_a, 0);
+
+        let mut register_a: u128 = 1 << 100;
+        let mut register_b: u128 = 99;
+        let mut register_c: u128 = 0;
+        let mut instruction_pointer = 0;
+        let _ = cdv(
+            5,
+            &register_a,
+            &register_b,
+            &mut register_c,
+            &mut instruction_pointer,
+        );
+        assert_eq!(register_c, 2);
+        register_b = u128::MAX;
+        let _ = adv(
+            5,
+            &mut register_a,
+            &register_b,
+            &register_c,
+            &mut instruction_pointer,
+        );
+        assert_eq!(register_a, 0);
+    }
+
+    #[test]
+    fn test_wide_words() {
+        // Same program as the example, with A shifted far beyond 64 bits
+        let instructions = vec![0, 3, 5, 4, 3, 0];
+        let mut register_a: u128 = 117440 << 90;
+        let result = process_instructions(&instructions, &mut register_a, &mut 0, &mut 0, &mut 0);
+        assert_eq!(result.len(), 36);
+        assert!(result.ends_with(&[0, 3, 5, 4, 3, 0]));
+
+        let mut register_a = BigUint::from(117440u64) << 300;
+        let result = process_instructions(
+            &instructions,
+            &mut register_a,
+            &mut BigUint::default(),
+            &mut BigUint::default(),
+            &mut 0,
+        );
+        assert_eq!(result.len(), 106);
+        assert!(result.ends_with(&[0, 3, 5, 4, 3, 0]));
+        assert_eq!(register_a, BigUint::ZERO);
+    }
+
+    #[test]
+    fn test_process_instructions_checked() {
+        let instructions = vec![3, 0];
+        let result = process_instructions_checked(
+            &instructions,
+            &mut 1u64,
+            &mut 0,
+            &mut 0,
+            &mut 0,
+            &ExecutionLimits::default(),
+        );
+        assert_eq!(
+            result,
+            Err(ExecutionError::InfiniteLoop {
+                start_step: 0,
+                length: 1,
+                instruction_pointer: 0,
+            })
+        );
+
+        // Toggles B between 0 and 1 and prints it, forever
+        let instructions = vec![1, 1, 2, 5, 5, 5, 3, 0];
+        let result = process_instructions_checked(
+            &instructions,
+            &mut 1u64,
+            &mut 0,
+            &mut 0,
+            &mut 0,
+            &ExecutionLimits::default(),
+        );
+        assert_eq!(
+            result,
+            Err(ExecutionError::InfiniteLoop {
+                start_step: 0,
+                length: 8,
+                instruction_pointer: 0,
+            })
+        );
+
+        let limits = ExecutionLimits {
+            max_steps: Some(100),
+            detect_loops: false,
+        };
+        let result =
+            process_instructions_checked(&instructions, &mut 1u64, &mut 0, &mut 0, &mut 0, &limits);
+        assert_eq!(
+            result,
+            Err(ExecutionError::StepLimitExceeded { steps: 100 })
+        );
+
+        let instructions = vec![0, 1, 5, 4, 3, 0];
+        let result = process_instructions_checked(
+            &instructions,
+            &mut 729u64,
+            &mut 0,
+            &mut 0,
+            &mut 0,
+            &ExecutionLimits::default(),
+        );
+        assert_eq!(result, Ok(vec![4, 6, 3, 5, 6, 3, 5, 2, 1, 0]));
+    }
+
+    #[test]
+    fn test_compiled_matches_interpreter() {
+        let programs = vec![
+            vec![0, 1, 5, 4, 3, 0],
+            vec![0, 3, 5, 4, 3, 0],
+            vec![2, 4, 1, 1, 7, 5, 4, 0, 0, 3, 1, 6, 5, 5, 3, 0],
+            vec![2, 4, 1, 3, 7, 5, 1, 5, 0, 3, 4, 3, 5, 5, 3, 0],
+            vec![6, 2, 3, 7, 0, 0, 0, 3, 6, 3],
+        ];
+        for program in programs {
+            let compiled = CompiledProgram::compile(&program);
+            for a in [0u64, 1, 7, 729, 2024, 117440, 1 << 40, u64::MAX] {
+                let (mut register_a, mut register_b, mut register_c) = (a, 3, 11);
+                let expected = process_instructions(
+                    &program,
+                    &mut register_a,
+                    &mut register_b,
+                    &mut register_c,
+                    &mut 0,
+                );
+                let mut registers = [a, 3, 11];
+                let result = compiled.run(&mut registers, None).unwrap();
+                assert_eq!(result, expected);
+                assert_eq!(registers, [register_a, register_b, register_c]);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release -- --ignored --nocapture"]
+    fn bench_compiled_against_interpreter() {
+        let program = vec![2, 4, 1, 3, 7, 5, 1, 5, 0, 3, 4, 3, 5, 5, 3, 0];
+        let runs = 1_000_000u64;
+
+        let start = Instant::now();
+        let mut interpreted = 0;
+        for a in 0..runs {
+            interpreted +=
+                process_instructions(&program, &mut (a << 20), &mut 0, &mut 0, &mut 0).len();
+        }
+        let interpreter_time = start.elapsed();
+
+        let compiled = CompiledProgram::compile(&program);
+        let start = Instant::now();
+        let mut compiled_total = 0;
+        for a in 0..runs {
+            compiled_total += compiled.run(&mut [a << 20, 0, 0], None).unwrap().len();
+        }
+        let compiled_time = start.elapsed();
+
+        assert_eq!(interpreted, compiled_total);
+        println!("interpreter: {interpreter_time:?}, compiled: {compiled_time:?}");
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Case {
+        program: Vec<usize>,
+        registers: [u64; 3],
+    }
+
+    const FUZZ_STEPS: usize = 500;
+
+    /// Straight from the puzzle text, kept separate from the code under test.
+    /// Returns `None` for output if the step budget runs out.
+    fn reference_run(case: &Case) -> (Option<Vec<usize>>, [u64; 3]) {
+        let program = &case.program;
+        let [mut a, mut b, mut c] = case.registers;
+        let mut ip = 0;
+        let mut output = Vec::new();
+        for _ in 0..FUZZ_STEPS {
+            if ip >= program.len() {
+                return (Some(output), [a, b, c]);
+            }
+            let literal = program[ip + 1] as u64;
+            // Operand 7 is reserved and only an error where a combo is read
+            let combo = || match literal {
+                0..=3 => literal,
+                4 => a,
+                5 => b,
+                6 => c,
+                _ => panic!("Invalid combo operand"),
+            };
+            let divide = |numerator: u64| {
+                let shift = combo();
+                if shift >= 64 {
+                    0
+                } else {
+                    numerator >> shift
+                }
+            };
+            ip += 2;
+            match program[ip - 2] {
+                0 => a = divide(a),
+                1 => b ^= literal,
+                2 => b = combo() % 8,
+                3 => {
+                    if a != 0 {
+                        ip = literal as usize;
+                    }
+                }
+                4 => b ^= c,
+                5 => output.push((combo() % 8) as usize),
+                6 => b = divide(a),
+                _ => c = divide(a),
+            }
+        }
+        if ip >= program.len() {
+            return (Some(output), [a, b, c]);
+        }
+        (None, [a, b, c])
+    }
+
+    /// Whether the program is decodable: combo operands are never 7 and jumps
+    /// land on even addresses.
+    fn decodable(program: &[usize]) -> bool {
+        program.chunks(2).all(|pair| match pair {
+            [1 | 4, _] => true,
+            [3, target] => target % 2 == 0,
+            [_, operand] => *operand <= 6,
+            _ => false,
+        })
+    }
+
+    /// Programs are always `decodable`.
+    fn random_case(rng: &mut XorShift) -> Case {
+        let mut program = Vec::new();
+        for _ in 0..1 + rng.below(8) {
+            let opcode = rng.below(8);
+            let operand = match opcode {
+                1 | 4 => rng.below(8),
+                3 => 2 * rng.below(4),
+                _ => rng.below(7),
+            };
+            program.push(opcode);
+            program.push(operand);
+        }
+        let mut registers = [0; 3];
+        for register in registers.iter_mut() {
+            let bits = rng.below(65) as u32;
+            *register = rng.next_u64().checked_shr(64 - bits).unwrap_or(0);
+        }
+        Case { program, registers }
+    }
+
+    fn case_fails(case: &Case) -> bool {
+        let (expected, expected_registers) = reference_run(case);
+        let [mut register_a, mut register_b, mut register_c] = case.registers;
+        let limits = ExecutionLimits {
+            max_steps: Some(FUZZ_STEPS),
+            detect_loops: false,
+        };
+        let result = process_instructions_checked(
+            &case.program,
+            &mut register_a,
+            &mut register_b,
+            &mut register_c,
+            &mut 0,
+            &limits,
+        );
+        let mut registers = case.registers;
+        let compiled =
+            CompiledProgram::compile(&case.program).run(&mut registers, Some(FUZZ_STEPS));
+        result.ok() != expected
+            || compiled.ok() != expected
+            || [register_a, register_b, register_c] != expected_registers
+            || registers != expected_registers
+    }
+
+    /// Greedily drops instructions and lowers values while the case still
+    /// fails, until no single change keeps it failing. Programs stay
+    /// `decodable` so a shrunk case fails for the same reason.
+    fn shrink(case: Case, fails: impl Fn(&Case) -> bool) -> Case {
+        let mut case = case;
+        loop {
+            let mut candidates = Vec::new();
+            for i in (0..case.program.len()).step_by(2) {
+                let mut candidate = case.clone();
+                candidate.program.drain(i..i + 2);
+                candidates.push(candidate);
+            }
+            for i in 0..case.program.len() {
+                for value in 0..case.program[i] {
+                    let mut candidate = case.clone();
+                    candidate.program[i] = value;
+                    candidates.push(candidate);
+                }
+            }
+            for i in 0..3 {
+                for value in [
+                    0,
+                    case.registers[i] / 2,
+                    case.registers[i] - case.registers[i].min(1),
+                ] {
+                    if value < case.registers[i] {
+                        let mut candidate = case.clone();
+                        candidate.registers[i] = value;
+                        candidates.push(candidate);
+                    }
+                }
+            }
+            match candidates
+                .into_iter()
+                .filter(|candidate| decodable(&candidate.program))
+                .find(|candidate| fails(candidate))
+            {
+                Some(smaller) => case = smaller,
+                None => return case,
+            }
+        }
+    }
+
+    #[test]
+    fn test_shrink() {
+        let case = Case {
+            program: vec![2, 4, 5, 5, 0, 3, 3, 0],
+            registers: [5000, 17, 3],
+        };
+        // Stand-in bug: anything that prints while A is at least 100
+        let fails = |case: &Case| {
+            case.registers[0] >= 100 && case.program.iter().step_by(2).any(|op| *op == 5)
+        };
+        let shrunk = shrink(case, fails);
+        assert_eq!(
+            shrunk,
+            Case {
+                program: vec![5, 0],
+                registers: [100, 0, 0],
+            }
+        );
+
+        // Lowering 4 to 0 in `4,7` or 2 to 1 in `3,2` would make the
+        // reference panic on an undecodable program
+        let case = Case {
+            program: vec![4, 7, 3, 2],
+            registers: [1, 0, 0],
+        };
+        let fails = |case: &Case| {
+            let _ = reference_run(case);
+            case.program.len() >= 4
+        };
+        let shrunk = shrink(case, fails);
+        assert_eq!(
+            shrunk,
+            Case {
+                program: vec![0, 0, 0, 0],
+                registers: [0, 0, 0],
+            }
+        );
+        assert!(decodable(&[1, 7, 4, 7, 3, 4]));
+        assert!(!decodable(&[2, 7]));
+        assert!(!decodable(&[3, 1]));
+    }
+
+    #[test]
+    fn test_differential_fuzz() {
+        let mut rng = XorShift(0x2024_0017);
+        for _ in 0..2000 {
+            let case = random_case(&mut rng);
+            if case_fails(&case) {
+                let minimal = shrink(case, case_fails);
+                panic!("interpreter disagrees with reference: {minimal:?}");
+            }
+        }
+    }
+
     #[test]
     fn test_part_1() {
         let result = part_1("data/day17/test.txt");
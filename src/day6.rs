@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::util::read_data_from_file;
 
@@ -66,6 +66,7 @@ fn iterate_path(
         match next_char(array, row, col, direction) {
             Some((next_row, next_col, ch)) => {
                 if ch == '#' {
+                    seen.insert((row, col, direction));
                     direction = match direction {
                         "up" => "right",
                         "right" => "down",
@@ -131,6 +132,8 @@ pub fn part_1() -> () {
     }
 }
 
+/// Step-by-step reference for `find_loops_fast`.
+#[cfg(test)]
 fn find_loops(array: &Vec<Vec<char>>) -> usize {
     let (row_num, col_num, direction) = find_starting_location(&array);
     let original_path: Vec<Vec<char>> = iterate_path(&array, row_num, col_num, direction).unwrap();
@@ -150,18 +153,307 @@ fn find_loops(array: &Vec<Vec<char>>) -> usize {
     count
 }
 
+const DIRECTIONS: [&str; 4] = ["up", "right", "down", "left"];
+const STEPS: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+/// For every cell and direction, the cell the guard stops on before the next
+/// obstacle, or `None` if it walks off the map. A route then costs one lookup
+/// per turn instead of one per step.
+struct JumpTable {
+    rows: usize,
+    cols: usize,
+    jumps: Vec<Option<(usize, usize)>>,
+}
+
+impl JumpTable {
+    fn new(array: &[Vec<char>]) -> JumpTable {
+        let rows = array.len();
+        let cols = array.first().map_or(0, |line| line.len());
+        let mut jumps = vec![None; rows * cols * 4];
+        for (d, (di, dj)) in STEPS.iter().enumerate() {
+            // Walk against the direction so the stop cell is known on arrival
+            let row_order: Vec<usize> = if *di > 0 {
+                (0..rows).rev().collect()
+            } else {
+                (0..rows).collect()
+            };
+            let col_order: Vec<usize> = if *dj > 0 {
+                (0..cols).rev().collect()
+            } else {
+                (0..cols).collect()
+            };
+            for &i in &row_order {
+                for &j in &col_order {
+                    let next = (i.checked_add_signed(*di), j.checked_add_signed(*dj));
+                    jumps[(i * cols + j) * 4 + d] = match next {
+                        (Some(ni), Some(nj)) if ni < rows && nj < cols => {
+                            if array[ni][nj] == '#' {
+                                Some((i, j))
+                            } else {
+                                jumps[(ni * cols + nj) * 4 + d]
+                            }
+                        }
+                        _ => None,
+                    };
+                }
+            }
+        }
+        JumpTable { rows, cols, jumps }
+    }
+
+    fn jump(&self, (i, j): (usize, usize), d: usize) -> Option<(usize, usize)> {
+        self.jumps[(i * self.cols + j) * 4 + d]
+    }
+
+    /// Where the guard stops from `from` heading `d`, with one extra obstacle.
+    /// The obstacle only matters if it sits between `from` and the usual stop.
+    fn jump_with(
+        &self,
+        from: (usize, usize),
+        d: usize,
+        obstacle: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let target = self.jump(from, d);
+        let (di, dj) = STEPS[d];
+        let ahead = |(i, j): (usize, usize)| -> Option<usize> {
+            let (dist_i, dist_j) = (i as isize - from.0 as isize, j as isize - from.1 as isize);
+            match (di, dj) {
+                (0, _) if dist_i == 0 && dist_j * dj > 0 => Some((dist_j * dj) as usize),
+                (_, 0) if dist_j == 0 && dist_i * di > 0 => Some((dist_i * di) as usize),
+                _ => None,
+            }
+        };
+        let Some(obstacle_distance) = ahead(obstacle) else {
+            return target;
+        };
+        let target_distance = match target {
+            Some(target) => ahead(target).unwrap_or(0),
+            None => self.rows.max(self.cols),
+        };
+        if obstacle_distance <= target_distance {
+            let steps = obstacle_distance as isize - 1;
+            Some((
+                from.0.checked_add_signed(di * steps).unwrap(),
+                from.1.checked_add_signed(dj * steps).unwrap(),
+            ))
+        } else {
+            target
+        }
+    }
+
+    /// Whether the guard loops forever once `obstacle` is added.
+    fn loops_with(&self, start: (usize, usize), direction: &str, obstacle: (usize, usize)) -> bool {
+        self.cycle_with(start, direction, obstacle).is_some()
+    }
+
+    /// The turns the guard repeats forever once `obstacle` is added, as
+    /// (stop cell, new direction) pairs, or `None` if it leaves the map.
+    fn cycle_with(
+        &self,
+        start: (usize, usize),
+        direction: &str,
+        obstacle: (usize, usize),
+    ) -> Option<Vec<((usize, usize), usize)>> {
+        let mut d = DIRECTIONS.iter().position(|x| *x == direction).unwrap();
+        let mut position = start;
+        let mut turns: Vec<((usize, usize), usize)> = Vec::new();
+        let mut seen: HashMap<((usize, usize), usize), usize> = HashMap::new();
+        loop {
+            position = self.jump_with(position, d, obstacle)?;
+            d = (d + 1) % 4;
+            if let Some(cycle_start) = seen.insert((position, d), turns.len()) {
+                return Some(turns.split_off(cycle_start));
+            }
+            turns.push((position, d));
+        }
+    }
+}
+
+/// An obstruction that traps the guard, with the loop it walks forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopObstruction {
+    pub position: (usize, usize),
+    /// Steps taken to go once around the loop.
+    pub loop_length: usize,
+    /// Cells in walking order, starting from a turn.
+    pub cycle: Vec<(usize, usize)>,
+    /// Each step of the loop as (from, to).
+    steps: Vec<((usize, usize), (usize, usize))>,
+}
+
+fn walk_cycle(turns: &[((usize, usize), usize)]) -> Vec<((usize, usize), (usize, usize))> {
+    let mut steps = Vec::new();
+    for (k, (from, d)) in turns.iter().enumerate() {
+        let to = turns[(k + 1) % turns.len()].0;
+        let (di, dj) = STEPS[*d];
+        let mut cell = *from;
+        while cell != to {
+            let next = (
+                cell.0.checked_add_signed(di).unwrap(),
+                cell.1.checked_add_signed(dj).unwrap(),
+            );
+            steps.push((cell, next));
+            cell = next;
+        }
+    }
+    steps
+}
+
+/// Every obstruction position that traps the guard, in row order.
+fn find_loop_obstructions(array: &Vec<Vec<char>>) -> Vec<LoopObstruction> {
+    let (row_num, col_num, direction) = find_starting_location(array);
+    let original_path: Vec<Vec<char>> = iterate_path(array, row_num, col_num, direction).unwrap();
+    let table = JumpTable::new(array);
+    let mut obstructions = Vec::new();
+    for position in x_inds(&original_path) {
+        if position == (row_num, col_num) {
+            continue;
+        }
+        if let Some(turns) = table.cycle_with((row_num, col_num), direction, position) {
+            let steps = walk_cycle(&turns);
+            let mut cycle: Vec<(usize, usize)> = steps.iter().map(|(from, _)| *from).collect();
+            if cycle.is_empty() {
+                // Boxed in, turning on the spot
+                cycle.push(turns[0].0);
+            }
+            obstructions.push(LoopObstruction {
+                position,
+                loop_length: steps.len(),
+                cycle,
+                steps,
+            });
+        }
+    }
+    obstructions
+}
+
+/// Draws the map in the style of the puzzle's examples: `X` for the original
+/// route, `|`, `-` and `+` for the loop and `O` for the new obstruction.
+fn render_obstruction(array: &Vec<Vec<char>>, obstruction: &LoopObstruction) -> String {
+    let (row_num, col_num, direction) = find_starting_location(array);
+    let mut picture = iterate_path(array, row_num, col_num, direction).unwrap();
+    let mut axes: HashMap<(usize, usize), (bool, bool)> = HashMap::new();
+    for (from, to) in &obstruction.steps {
+        let vertical = from.1 == to.1;
+        for cell in [from, to] {
+            let entry = axes.entry(*cell).or_default();
+            if vertical {
+                entry.0 = true;
+            } else {
+                entry.1 = true;
+            }
+        }
+    }
+    for ((i, j), axes) in axes {
+        picture[i][j] = match axes {
+            (true, false) => '|',
+            (false, true) => '-',
+            _ => '+',
+        };
+    }
+    picture[obstruction.position.0][obstruction.position.1] = 'O';
+    picture[row_num][col_num] = array[row_num][col_num];
+    picture
+        .iter()
+        .map(|line| line.iter().collect::<String>() + "\n")
+        .collect()
+}
+
+/// Same count as `find_loops`, checking each candidate with the jump table.
+/// Serial reference for `find_loops_parallel`.
+#[cfg(test)]
+fn find_loops_fast(array: &Vec<Vec<char>>) -> usize {
+    let (row_num, col_num, direction) = find_starting_location(array);
+    let original_path: Vec<Vec<char>> = iterate_path(array, row_num, col_num, direction).unwrap();
+    let table = JumpTable::new(array);
+    x_inds(&original_path)
+        .into_iter()
+        .filter(|(i, j)| (*i, *j) != (row_num, col_num))
+        .filter(|obstacle| table.loops_with((row_num, col_num), direction, *obstacle))
+        .count()
+}
+
+/// Worker count used when none is given: one per available core.
+fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Applies `f` to every candidate on a pool of `threads` scoped workers, each
+/// taking a contiguous chunk. Results come back in candidate order.
+fn evaluate_candidates<T, R, F>(candidates: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads.clamp(1, candidates.len().max(1));
+    let chunk_size = candidates.len().div_ceil(threads).max(1);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = candidates
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+/// `find_loops_fast` with the candidates split across `threads` workers,
+/// defaulting to the available parallelism.
+fn find_loops_parallel(array: &Vec<Vec<char>>, threads: Option<usize>) -> usize {
+    let (row_num, col_num, direction) = find_starting_location(array);
+    let original_path: Vec<Vec<char>> = iterate_path(array, row_num, col_num, direction).unwrap();
+    let table = JumpTable::new(array);
+    let candidates: Vec<(usize, usize)> = x_inds(&original_path)
+        .into_iter()
+        .filter(|(i, j)| (*i, *j) != (row_num, col_num))
+        .collect();
+    evaluate_candidates(
+        &candidates,
+        threads.unwrap_or_else(default_threads),
+        |obstacle| table.loops_with((row_num, col_num), direction, *obstacle),
+    )
+    .into_iter()
+    .filter(|loops| *loops)
+    .count()
+}
+
+/// Prints every obstruction that traps the guard, drawn on the map.
+pub fn report_loops(input_uri: &str) {
+    let input = read_data_from_file(input_uri);
+    let array = input_to_array(&input);
+    for obstruction in find_loop_obstructions(&array) {
+        println!(
+            "{:?}: loop of {} steps",
+            obstruction.position, obstruction.loop_length
+        );
+        println!("{}", render_obstruction(&array, &obstruction));
+    }
+}
+
 pub fn part_2() -> () {
     // add obstacles, somewhere, test if we're in a loop, and count the numbers of options
     // very tempted to brute force this
+    part_2_with_threads(None);
+}
+
+/// `part_2` checking candidates on `threads` workers, or one per available
+/// core when `None`. The count is the same whatever the thread count.
+pub fn part_2_with_threads(threads: Option<usize>) -> usize {
     let input = read_data_from_file("data/day6/puzzle.txt");
     let array = input_to_array(&input);
-    let count = find_loops(&array);
+    let count = find_loops_parallel(&array, threads);
     println!("{count}");
+    count
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::XorShift;
 
     #[test]
     fn part_1_works() {
@@ -191,5 +483,133 @@ mod tests {
         let array = input_to_array(&input);
         let answer = find_loops(&array);
         assert_eq!(answer, 6);
+        let answer = find_loops_fast(&array);
+        assert_eq!(answer, 6);
+    }
+
+    const EXAMPLE: &str = "....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...";
+
+    /// A map of 2 to `max_side + 1` cells a side with about one obstacle in
+    /// six and a guard facing any way, or `None` if the guard never leaves.
+    fn random_map(rng: &mut XorShift, max_side: usize) -> Option<Vec<Vec<char>>> {
+        let (rows, cols) = (2 + rng.below(max_side), 2 + rng.below(max_side));
+        let mut array: Vec<Vec<char>> = (0..rows)
+            .map(|_| {
+                (0..cols)
+                    .map(|_| if rng.below(6) == 0 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        array[rng.below(rows)][rng.below(cols)] = ['^', '>', 'v', '<'][rng.below(4)];
+        let (row_num, col_num, direction) = find_starting_location(&array);
+        iterate_path(&array, row_num, col_num, direction)?;
+        Some(array)
+    }
+
+    #[test]
+    fn test_iterate_path_boxed_in() {
+        // Walled in on all four sides the guard only turns, which is a loop
+        let array = input_to_array(".#.\n#^#\n.#.");
+        assert_eq!(iterate_path(&array, 1, 1, "up"), None);
+        // One way out and the guard turns until it finds it
+        let array = input_to_array(".#.\n#^.\n.#.");
+        let path = iterate_path(&array, 1, 1, "up").unwrap();
+        assert_eq!(sum_x(&path), 2);
+    }
+
+    #[test]
+    fn test_find_loops_fast() {
+        let array = input_to_array(EXAMPLE);
+        assert_eq!(find_loops_fast(&array), 6);
+
+        // Random maps, including starts boxed in and obstacles next to the start
+        let mut rng = XorShift(6);
+        for _ in 0..300 {
+            let Some(array) = random_map(&mut rng, 12) else {
+                continue;
+            };
+            assert_eq!(find_loops_fast(&array), find_loops(&array));
+        }
+    }
+
+    #[test]
+    fn test_find_loops_parallel() {
+        let array = input_to_array(EXAMPLE);
+        for threads in [None, Some(1), Some(2), Some(3), Some(64)] {
+            assert_eq!(find_loops_parallel(&array, threads), 6);
+        }
+
+        let mut rng = XorShift(42);
+        for _ in 0..100 {
+            let Some(array) = random_map(&mut rng, 20) else {
+                continue;
+            };
+            let threads = 1 + rng.below(8);
+            assert_eq!(
+                find_loops_parallel(&array, Some(threads)),
+                find_loops_fast(&array)
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_candidates_keeps_order() {
+        let candidates: Vec<usize> = (0..103).collect();
+        let serial: Vec<usize> = candidates.iter().map(|x| x * x).collect();
+        for threads in [0, 1, 4, 7, 200] {
+            assert_eq!(evaluate_candidates(&candidates, threads, |x| x * x), serial);
+        }
+        assert!(evaluate_candidates(&[] as &[usize], 4, |x| *x).is_empty());
+    }
+
+    #[test]
+    fn test_find_loop_obstructions() {
+        let array = input_to_array(EXAMPLE);
+        let obstructions = find_loop_obstructions(&array);
+        let positions: Vec<(usize, usize)> = obstructions.iter().map(|o| o.position).collect();
+        assert_eq!(
+            positions,
+            vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]
+        );
+
+        // The first option from the puzzle: a rectangle from row 1 to 6
+        let first = &obstructions[0];
+        assert_eq!(first.loop_length, 18);
+        assert_eq!(first.cycle.len(), 18);
+        assert!(first.cycle.contains(&(1, 4)) && first.cycle.contains(&(6, 8)));
+        assert_eq!(
+            render_obstruction(&array, first),
+            "....#.....
+....+---+#
+....|...|.
+..#.|...|.
+..XX|XX#|.
+..X.|.X.|.
+.#XO^---+.
+.XXXXXXX#.
+#XXXXXXX..
+......#X..
+"
+        );
+    }
+
+    #[test]
+    fn test_boxed_in_loop() {
+        // Blocking the only way out leaves the guard turning on the spot
+        let array = input_to_array(".#.\n#^.\n.#.");
+        let obstructions = find_loop_obstructions(&array);
+        assert_eq!(obstructions.len(), 1);
+        assert_eq!(obstructions[0].position, (1, 2));
+        assert_eq!(obstructions[0].loop_length, 0);
+        assert_eq!(obstructions[0].cycle, vec![(1, 1)]);
     }
 }
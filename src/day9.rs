@@ -1,38 +1,47 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use std::fs;
+
 use crate::util::read_data_from_file;
 
-#[derive(Debug, Clone)]
-struct File {
-    free_blocks: usize,
-    blocks: Vec<usize>,
+/// A single block on the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block {
+    Free,
+    File(usize),
 }
 
-impl File {
-    fn new(id: usize, filled_blocks: usize, free_blocks: usize) -> Self {
-        let mut blocks: Vec<usize> = Vec::new();
-        for i in 0..filled_blocks {
-            blocks.insert(i, id);
-        }
-        File {
-            free_blocks,
-            blocks,
-        }
-    }
+/// A file's place in the original layout, followed by `free_blocks` of gap.
+#[derive(Debug, Clone, PartialEq)]
+struct File {
+    id: usize,
+    start: usize,
+    len: usize,
+    free_blocks: usize,
 }
 
 fn files(file_map: Vec<usize>) -> Vec<File> {
     let mut files: Vec<File> = Vec::new();
+    let mut start = 0;
     for (id, filled_blocks) in file_map.iter().step_by(2).enumerate() {
         let free_blocks = match file_map.get(id * 2 + 1) {
             Some(a) => *a,
             None => 0,
         };
-        files.insert(id, File::new(id, *filled_blocks, free_blocks));
+        files.push(File {
+            id,
+            start,
+            len: *filled_blocks,
+            free_blocks,
+        });
+        start += filled_blocks + free_blocks;
     }
     files
 }
 
 fn file_map(input: &str) -> Vec<usize> {
     input
+        .trim()
         .chars()
         .map(|x| {
             x.to_string()
@@ -42,87 +51,384 @@ fn file_map(input: &str) -> Vec<usize> {
         .collect::<Vec<usize>>()
 }
 
-pub fn part_1(input_uri: &str) -> usize {
-    let input = read_data_from_file(input_uri);
-    let fmap = file_map(&input);
-    let mut files = files(fmap);
+/// Expands the files into one block per disk position.
+fn layout(files: &[File]) -> Vec<Block> {
+    let mut disk = Vec::new();
+    for file in files {
+        disk.extend(std::iter::repeat_n(Block::File(file.id), file.len));
+        disk.extend(std::iter::repeat_n(Block::Free, file.free_blocks));
+    }
+    disk
+}
 
+fn checksum(disk: &[Block]) -> usize {
+    disk.iter()
+        .enumerate()
+        .map(|(i, block)| match block {
+            Block::File(id) => i * id,
+            Block::Free => 0,
+        })
+        .sum()
+}
+
+/// Moves single blocks from the end of the disk into the leftmost free block
+/// until there are no gaps, calling `on_move` after each one.
+fn compact_blocks(disk: &mut [Block], on_move: &mut impl FnMut(&[Block])) {
     let mut forward_index: usize = 0;
-    let mut backward_index: usize = files.len() - 1;
-
-    while forward_index != backward_index {
-        if files[forward_index].free_blocks > 0 {
-            if files[backward_index].blocks.len() > 0 {
-                let block = files[backward_index].blocks.remove(0);
-                files[forward_index].blocks.push(block);
-                files[forward_index].free_blocks -= 1;
-            } else {
-                backward_index -= 1;
-            }
-        } else {
+    let mut backward_index: usize = disk.len();
+    loop {
+        while forward_index < backward_index && disk[forward_index] != Block::Free {
             forward_index += 1;
         }
+        while backward_index > forward_index && disk[backward_index - 1] == Block::Free {
+            backward_index -= 1;
+        }
+        if backward_index - forward_index < 2 {
+            break;
+        }
+        disk.swap(forward_index, backward_index - 1);
+        on_move(disk);
     }
-    let checksum = files
-        .iter()
-        .map(|x| &x.blocks)
-        .flatten()
-        .enumerate()
-        .map(|(i, x)| i * x)
-        .sum::<usize>();
-    checksum
 }
 
-pub fn part_2(input_uri: &str) -> usize {
-    let input = read_data_from_file(input_uri);
-    let fmap = file_map(&input);
-    let mut files = files(fmap);
-
-    let mut backward_index = files.len();
-
-    while backward_index > 0 {
-        let backwards_blocks = files[backward_index - 1].blocks.clone();
-        let num_original_blocks = backwards_blocks
-            .iter()
-            .filter(|x| **x == backward_index - 1)
-            .collect::<Vec<&usize>>()
-            .len();
-        for forward_index in 0..backward_index - 1 {
-            if files[forward_index].free_blocks >= num_original_blocks {
-                for (i, v) in backwards_blocks.iter().enumerate() {
-                    if *v == backward_index - 1 {
-                        files[forward_index].free_blocks -= 1;
-                        files[forward_index].blocks.push(*v);
-                        files[backward_index - 1].blocks[i] = 0;
-                    }
-                }
-                break;
-            }
+/// How a whole file picks a free span to the left of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Single blocks from the end into the leftmost gap, as in `part_1`.
+    Blocks,
+    /// The leftmost span that fits, as in `part_2`.
+    FirstFit,
+    /// The smallest span that fits, leftmost on ties.
+    BestFit,
+    /// The largest span, leftmost on ties.
+    WorstFit,
+    /// The first span that fits at or after the end of the last move,
+    /// wrapping round to the start of the disk.
+    NextFit,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 5] = [
+        Strategy::Blocks,
+        Strategy::FirstFit,
+        Strategy::BestFit,
+        Strategy::WorstFit,
+        Strategy::NextFit,
+    ];
+}
+
+/// Hands out free spans for whole-file moves.
+trait Allocator {
+    /// Removes a span of at least `len` blocks starting before `before`,
+    /// keeping any leftover, and returns its start.
+    fn take(&mut self, len: usize, before: usize) -> Option<usize>;
+}
+
+/// The maximal runs of free blocks as (start, length).
+fn free_spans(disk: &[Block]) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < disk.len() {
+        let start = i;
+        while i < disk.len() && disk[i] == Block::Free {
+            i += 1;
+        }
+        if i > start {
+            spans.push((start, i - start));
+        } else {
+            i += 1;
+        }
+    }
+    spans
+}
+
+/// Free spans bucketed by length, each bucket a min-heap of start positions,
+/// so a fitting span is found by checking one heap top per length.
+struct FreeIndex {
+    heaps: Vec<BinaryHeap<Reverse<usize>>>,
+    strategy: Strategy,
+}
+
+impl FreeIndex {
+    fn new(disk: &[Block], strategy: Strategy) -> Self {
+        let mut index = FreeIndex {
+            heaps: Vec::new(),
+            strategy,
+        };
+        for (start, len) in free_spans(disk) {
+            index.insert(start, len);
+        }
+        index
+    }
+
+    fn insert(&mut self, start: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        if self.heaps.len() <= len {
+            self.heaps.resize_with(len + 1, BinaryHeap::new);
+        }
+        self.heaps[len].push(Reverse(start));
+    }
+}
+
+impl Allocator for FreeIndex {
+    fn take(&mut self, len: usize, before: usize) -> Option<usize> {
+        // The top of each heap is the leftmost span of that length
+        let mut candidates = (len.max(1)..self.heaps.len())
+            .filter_map(|size| self.heaps[size].peek().map(|Reverse(start)| (size, *start)))
+            .filter(|(_, start)| *start < before);
+        let (size, start) = match self.strategy {
+            Strategy::BestFit => candidates.next(),
+            Strategy::WorstFit => candidates.next_back(),
+            _ => candidates.min_by_key(|(_, start)| *start),
+        }?;
+        self.heaps[size].pop();
+        self.insert(start + len, size - len);
+        Some(start)
+    }
+}
+
+/// Free spans by start position with a cursor left at the end of the last
+/// move, for next-fit.
+struct RovingIndex {
+    spans: BTreeMap<usize, usize>,
+    cursor: usize,
+}
+
+impl RovingIndex {
+    fn new(disk: &[Block]) -> Self {
+        RovingIndex {
+            spans: free_spans(disk).into_iter().collect(),
+            cursor: 0,
+        }
+    }
+}
+
+impl Allocator for RovingIndex {
+    fn take(&mut self, len: usize, before: usize) -> Option<usize> {
+        let fits = |(start, size): (&usize, &usize)| (*size >= len).then_some((*start, *size));
+        let (start, size) = self
+            .spans
+            .range(self.cursor.min(before)..before)
+            .find_map(fits)
+            .or_else(|| self.spans.range(..self.cursor.min(before)).find_map(fits))?;
+        self.spans.remove(&start);
+        if size > len {
+            self.spans.insert(start + len, size - len);
+        }
+        self.cursor = start + len;
+        Some(start)
+    }
+}
+
+/// Moves each whole file, highest id first, into a free span further left
+/// chosen by `allocator`, calling `on_move` after each one.
+fn compact_files(
+    disk: &mut [Block],
+    files: &[File],
+    allocator: &mut impl Allocator,
+    on_move: &mut impl FnMut(&[Block]),
+) {
+    for file in files.iter().rev() {
+        if file.len == 0 {
+            continue;
+        }
+        if let Some(target) = allocator.take(file.len, file.start) {
+            disk[target..target + file.len].fill(Block::File(file.id));
+            disk[file.start..file.start + file.len].fill(Block::Free);
+            on_move(disk);
+        }
+    }
+}
+
+/// The compacted layout under `strategy`, with `on_move` seeing the disk
+/// after every move.
+fn compact_observed(
+    input: &str,
+    strategy: Strategy,
+    on_move: &mut impl FnMut(&[Block]),
+) -> Vec<Block> {
+    let files = files(file_map(input));
+    let mut disk = layout(&files);
+    match strategy {
+        Strategy::Blocks => compact_blocks(&mut disk, on_move),
+        Strategy::NextFit => {
+            let mut allocator = RovingIndex::new(&disk);
+            compact_files(&mut disk, &files, &mut allocator, on_move)
+        }
+        _ => {
+            let mut allocator = FreeIndex::new(&disk, strategy);
+            compact_files(&mut disk, &files, &mut allocator, on_move)
+        }
+    }
+    disk
+}
+
+/// The compacted layout under `strategy`.
+fn compact(input: &str, strategy: Strategy) -> Vec<Block> {
+    compact_observed(input, strategy, &mut |_| {})
+}
+
+/// The layout before compaction, after every move if `every_move` is set,
+/// and after compaction.
+fn snapshots(input: &str, strategy: Strategy, every_move: bool) -> Vec<Vec<Block>> {
+    let mut snapshots = vec![layout(&files(file_map(input)))];
+    let after = compact_observed(input, strategy, &mut |disk| {
+        if every_move {
+            snapshots.push(disk.to_vec());
         }
-        backward_index -= 1;
+    });
+    if snapshots.last() != Some(&after) || snapshots.len() == 1 {
+        snapshots.push(after);
     }
+    snapshots
+}
+
+/// Disks up to this many blocks are also printed in dotted notation.
+const DOTTED_MAX_BLOCKS: usize = 200;
+
+/// Renders the disk as in the puzzle, `00...111...2...333`, with file ids
+/// past 9 as letters. `None` if an id has no single-character glyph.
+pub fn to_dotted(disk: &[Block]) -> Option<String> {
+    disk.iter()
+        .map(|block| match block {
+            Block::Free => Some('.'),
+            Block::File(id) => char::from_digit(u32::try_from(*id).ok()?, 36),
+        })
+        .collect()
+}
 
-    for file in files.iter_mut() {
-        if file.free_blocks > 0 {
-            for _ in 0..file.free_blocks {
-                file.blocks.push(0);
-                file.free_blocks = 0;
+/// Reads a disk back from dotted notation.
+pub fn parse_dotted(text: &str) -> Option<Vec<Block>> {
+    text.trim()
+        .chars()
+        .map(|ch| match ch {
+            '.' => Some(Block::Free),
+            _ => ch.to_digit(36).map(|id| Block::File(id as usize)),
+        })
+        .collect()
+}
+
+/// A bright, stable color for each file id; free blocks are black.
+fn block_color(block: &Block) -> [u8; 3] {
+    match block {
+        Block::Free => [0, 0, 0],
+        Block::File(id) => {
+            let hash = (*id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            let channel = |shift: u32| 64 + ((hash >> shift) % 192) as u8;
+            [channel(40), channel(48), channel(56)]
+        }
+    }
+}
+
+/// A binary PPM strip `height` pixels tall with one column per block.
+fn to_ppm(disk: &[Block], height: usize) -> Vec<u8> {
+    let mut image = format!("P6\n{} {}\n255\n", disk.len(), height).into_bytes();
+    let row: Vec<u8> = disk.iter().flat_map(block_color).collect();
+    for _ in 0..height {
+        image.extend_from_slice(&row);
+    }
+    image
+}
+
+/// Writes `before.ppm`, `after.ppm` and, if `every_move` is set,
+/// `move_NNNNNN.ppm` strips into `out_dir`. Small disks are also printed in
+/// dotted notation.
+pub fn render_compaction(
+    input_uri: &str,
+    strategy: Strategy,
+    out_dir: &str,
+    every_move: bool,
+) -> std::io::Result<()> {
+    let input = read_data_from_file(input_uri);
+    let snapshots = snapshots(&input, strategy, every_move);
+    let last = snapshots.len() - 1;
+    fs::create_dir_all(out_dir)?;
+    for (i, disk) in snapshots.iter().enumerate() {
+        let name = match i {
+            0 => "before".to_string(),
+            _ if i == last => "after".to_string(),
+            _ => format!("move_{i:06}"),
+        };
+        fs::write(format!("{out_dir}/{name}.ppm"), to_ppm(disk, 16))?;
+        if disk.len() <= DOTTED_MAX_BLOCKS {
+            if let Some(dotted) = to_dotted(disk) {
+                println!("{dotted}");
             }
         }
     }
-    let checksum = files
+    Ok(())
+}
+
+/// The outcome of compacting a disk with one strategy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionReport {
+    pub strategy: Strategy,
+    pub checksum: usize,
+    /// Files with at least one block in a new place.
+    pub moved_files: usize,
+    /// Runs of free blocks left, including the free tail of the disk.
+    pub free_spans: usize,
+    pub largest_free_span: usize,
+}
+
+fn compaction_report(input: &str, strategy: Strategy) -> CompactionReport {
+    let before = layout(&files(file_map(input)));
+    let after = compact(input, strategy);
+    let moved: HashSet<usize> = before
+        .iter()
+        .zip(after.iter())
+        .filter(|(b, a)| b != a)
+        .filter_map(|(_, a)| match a {
+            Block::File(id) => Some(*id),
+            Block::Free => None,
+        })
+        .collect();
+    let spans = free_spans(&after);
+    CompactionReport {
+        strategy,
+        checksum: checksum(&after),
+        moved_files: moved.len(),
+        free_spans: spans.len(),
+        largest_free_span: spans.iter().map(|(_, len)| *len).max().unwrap_or(0),
+    }
+}
+
+/// Compacts the disk with every strategy and prints a line for each.
+pub fn compare_strategies(input_uri: &str) -> Vec<CompactionReport> {
+    let input = read_data_from_file(input_uri);
+    let reports: Vec<CompactionReport> = Strategy::ALL
         .iter()
-        .map(|x| &x.blocks)
-        .flatten()
-        .enumerate()
-        .map(|(i, x)| i * x)
-        .sum::<usize>();
-    checksum
+        .map(|strategy| compaction_report(&input, *strategy))
+        .collect();
+    for report in &reports {
+        println!(
+            "{:<10} checksum {:>16} moved {:>6} free spans {:>6} largest {:>4}",
+            format!("{:?}", report.strategy),
+            report.checksum,
+            report.moved_files,
+            report.free_spans,
+            report.largest_free_span
+        );
+    }
+    reports
+}
+
+pub fn part_1(input_uri: &str) -> usize {
+    let input = read_data_from_file(input_uri);
+    checksum(&compact(&input, Strategy::Blocks))
+}
+
+pub fn part_2(input_uri: &str) -> usize {
+    let input = read_data_from_file(input_uri);
+    checksum(&compact(&input, Strategy::FirstFit))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::XorShift;
 
     #[test]
     fn test_file_map() {
@@ -153,4 +459,184 @@ mod tests {
         let result = part_2("data/day9/test.txt");
         assert_eq!(result, 2858);
     }
+
+    const EXAMPLE: &str = "2333133121414131402";
+
+    fn to_string(disk: &[Block]) -> String {
+        to_dotted(disk).unwrap()
+    }
+
+    /// Straightforward rescan of the gaps for every file.
+    fn reference_compact_files(disk: &mut [Block], files: &[File], strategy: Strategy) {
+        let mut cursor = 0;
+        for file in files.iter().rev() {
+            let current = disk.iter().position(|b| *b == Block::File(file.id));
+            let Some(current) = current else { continue };
+            let fitting: Vec<(usize, usize)> = free_spans(disk)
+                .into_iter()
+                .filter(|(start, len)| *start < current && *len >= file.len)
+                .collect();
+            let chosen = match strategy {
+                Strategy::FirstFit => fitting.first().copied(),
+                Strategy::BestFit => fitting.iter().copied().min_by_key(|(_, len)| *len),
+                Strategy::WorstFit => fitting
+                    .iter()
+                    .copied()
+                    .min_by_key(|(_, len)| usize::MAX - len),
+                Strategy::NextFit => fitting
+                    .iter()
+                    .copied()
+                    .find(|(start, _)| *start >= cursor)
+                    .or(fitting.first().copied()),
+                Strategy::Blocks => unreachable!(),
+            };
+            if let Some((target, _)) = chosen {
+                disk[target..target + file.len].fill(Block::File(file.id));
+                disk[current..current + file.len].fill(Block::Free);
+                cursor = target + file.len;
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_example() {
+        let files = files(file_map(EXAMPLE));
+        assert_eq!(
+            to_string(&layout(&files)),
+            "00...111...2...333.44.5555.6666.777.888899"
+        );
+
+        let disk = compact(EXAMPLE, Strategy::Blocks);
+        assert_eq!(
+            to_string(&disk),
+            "0099811188827773336446555566.............."
+        );
+        assert_eq!(checksum(&disk), 1928);
+
+        let disk = compact(EXAMPLE, Strategy::FirstFit);
+        assert_eq!(
+            to_string(&disk),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert_eq!(checksum(&disk), 2858);
+        assert_eq!(checksum(&compact("12345", Strategy::Blocks)), 60);
+    }
+
+    #[test]
+    fn test_free_is_not_file_zero() {
+        // An empty file 0 leaves nothing but free blocks behind file 1
+        for strategy in Strategy::ALL {
+            let disk = compact("0212", strategy);
+            assert_eq!(disk[0], Block::File(1));
+            assert!(disk[1..].iter().all(|b| *b == Block::Free));
+            assert!(!disk.contains(&Block::File(0)));
+        }
+        let disk = compact("2112", Strategy::FirstFit);
+        assert_eq!(to_string(&disk), "001...");
+    }
+
+    #[test]
+    fn test_compact_files_matches_reference() {
+        let mut rng = XorShift(9);
+        for _ in 0..500 {
+            let input: String = (0..1 + rng.below(40))
+                .map(|_| char::from_digit(rng.below(10) as u32, 10).unwrap())
+                .collect();
+            let files = files(file_map(&input));
+            for strategy in &Strategy::ALL[1..] {
+                let mut expected = layout(&files);
+                reference_compact_files(&mut expected, &files, *strategy);
+                assert_eq!(compact(&input, *strategy), expected, "{input} {strategy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        // Gaps of 2, 1 and 3 in front of a one-block file
+        let render = |input, strategy| to_string(&compact(input, strategy));
+        assert_eq!(render("1211131", Strategy::FirstFit), "0321......");
+        assert_eq!(render("1211131", Strategy::BestFit), "021.3.....");
+        assert_eq!(render("1211131", Strategy::WorstFit), "021...3...");
+        // Next fit carries on from the end of the last move before wrapping
+        assert_eq!(render("111211102", Strategy::FirstFit), "031442....");
+        assert_eq!(render("111211102", Strategy::NextFit), "02144.3...");
+
+        // The example is the same for every whole-file strategy
+        for strategy in &Strategy::ALL[1..] {
+            assert_eq!(
+                compaction_report(EXAMPLE, *strategy),
+                CompactionReport {
+                    strategy: *strategy,
+                    checksum: 2858,
+                    moved_files: 4,
+                    free_spans: 6,
+                    largest_free_span: 5,
+                }
+            );
+        }
+        let report = compaction_report(EXAMPLE, Strategy::Blocks);
+        assert_eq!(report.checksum, 1928);
+        assert_eq!(report.moved_files, 4);
+        assert_eq!(report.free_spans, 1);
+        assert_eq!(report.largest_free_span, 14);
+        let report = compaction_report("1211131", Strategy::WorstFit);
+        assert_eq!(report.checksum, 22);
+        assert_eq!(report.moved_files, 3);
+        assert_eq!(report.free_spans, 2);
+        assert_eq!(report.largest_free_span, 3);
+    }
+
+    #[test]
+    fn test_dotted_round_trip() {
+        let files = files(file_map(EXAMPLE));
+        let disk = layout(&files);
+        assert_eq!(parse_dotted(&to_string(&disk)), Some(disk));
+        assert_eq!(
+            parse_dotted("0.a"),
+            Some(vec![Block::File(0), Block::Free, Block::File(10)])
+        );
+        assert_eq!(parse_dotted("0#1"), None);
+        assert_eq!(to_dotted(&[Block::File(36)]), None);
+    }
+
+    #[test]
+    fn test_snapshots() {
+        // The steps shown in the puzzle for whole-file compaction
+        let expected: Vec<Vec<Block>> = [
+            "00...111...2...333.44.5555.6666.777.888899",
+            "0099.111...2...333.44.5555.6666.777.8888..",
+            "0099.1117772...333.44.5555.6666.....8888..",
+            "0099.111777244.333....5555.6666.....8888..",
+            "00992111777.44.333....5555.6666.....8888..",
+        ]
+        .iter()
+        .map(|text| parse_dotted(text).unwrap())
+        .collect();
+        assert_eq!(snapshots(EXAMPLE, Strategy::FirstFit, true), expected);
+        assert_eq!(
+            snapshots(EXAMPLE, Strategy::FirstFit, false),
+            vec![expected[0].clone(), expected[4].clone()]
+        );
+
+        let steps = snapshots("12345", Strategy::Blocks, true);
+        assert_eq!(to_string(&steps[1]), "02.111....2222.");
+        assert_eq!(to_string(steps.last().unwrap()), "022111222......");
+        // Nothing to move still gives a before and after
+        assert_eq!(snapshots("1", Strategy::FirstFit, true).len(), 2);
+    }
+
+    #[test]
+    fn test_to_ppm() {
+        let disk = parse_dotted("0.1").unwrap();
+        let image = to_ppm(&disk, 2);
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        assert_eq!(image.len(), header.len() + 3 * 2 * 3);
+        let pixels = &image[header.len()..];
+        assert_eq!(&pixels[3..6], &[0, 0, 0]);
+        assert_eq!(&pixels[..9], &pixels[9..]);
+        assert_ne!(&pixels[..3], &pixels[6..9]);
+        assert!(pixels[..3].iter().all(|c| *c >= 64));
+    }
 }
@@ -34,7 +34,7 @@ pub fn input_to_grid_map(input: &str) -> HashMap<(usize, usize), char> {
     grid
 }
 
-pub fn display_grid_map(grid: &HashMap<(usize, usize), char>) {
+pub fn grid_map_to_string(grid: &HashMap<(usize, usize), char>) -> String {
     let mut vec_grid: Vec<Vec<char>> = Vec::new();
     let max_x = grid.keys().map(|(x, _)| x).max().unwrap();
     let max_y = grid.keys().map(|(_, y)| y).max().unwrap();
@@ -45,10 +45,16 @@ pub fn display_grid_map(grid: &HashMap<(usize, usize), char>) {
             vec_grid[i].push(*ch);
         }
     }
+    let mut s = String::new();
     for line in vec_grid {
-        let s = line.iter().collect::<String>();
-        println!("{s}");
+        s.extend(line);
+        s.push('\n');
     }
+    s
+}
+
+pub fn display_grid_map(grid: &HashMap<(usize, usize), char>) {
+    print!("{}", grid_map_to_string(grid));
 }
 
 pub fn find_char(grid: &HashMap<(usize, usize), char>, ch: char) -> Option<(usize, usize)> {
@@ -88,3 +94,22 @@ pub fn min_in_hashset<T: Hash + Eq + Copy>(
     }
     min_node
 }
+
+/// Seeded xorshift so randomised tests see the same cases on every run.
+#[cfg(test)]
+pub struct XorShift(pub u64);
+
+#[cfg(test)]
+impl XorShift {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform-ish in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}